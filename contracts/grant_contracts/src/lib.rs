@@ -1,20 +1,19 @@
 #![no_std]
-
-#[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    loop {}
-}
+// Contract entrypoints take their parameters flat, so some need more than seven.
+#![allow(clippy::too_many_arguments)]
 
 use soroban_sdk::{
-    contract, contracterror, contractimpl, contracttype, symbol_short, token, vec, Address, Env,
-    Vec,
+    contract, contracterror, contractimpl, contracttype, symbol_short, token, vec, Address,
+    BytesN, Env, IntoVal, InvokeError, Map, String, Symbol, Vec,
+};
+
 pub mod optimized;
+#[cfg(test)]
 pub mod benchmarks;
 pub mod self_terminate;
 
 // Re-export optimized implementation
 pub use optimized::{
-    GrantContract, Grant, Error, DataKey,
     STATUS_ACTIVE, STATUS_PAUSED, STATUS_COMPLETED, STATUS_CANCELLED,
     STATUS_REVOCABLE, STATUS_MILESTONE_BASED, STATUS_AUTO_RENEW, STATUS_EMERGENCY_PAUSE,
     has_status, set_status, clear_status, toggle_status,
@@ -28,14 +27,27 @@ pub use self_terminate::{
 };
 
 #[cfg(test)]
-pub use test_optimized::*;
+mod test_optimized;
 #[cfg(test)]
-pub use test_self_terminate::*;
+mod test_self_terminate;
+
 /// Scaling factor for high-precision flow rate calculations.
 /// This prevents zero flow rates when dealing with low-decimal tokens.
 /// Flow rates are stored as scaled values (multiplied by this factor).
 pub const SCALING_FACTOR: i128 = 10_000_000; // 1e7
 
+/// Sub-token units tracked by settle_grant: scaled flow rate times the warmup multiplier in bps.
+const ACCRUAL_PRECISION: i128 = SCALING_FACTOR * FULL_RATE_BPS;
+
+/// 100% of the flow rate, in basis points.
+const FULL_RATE_BPS: i128 = 10_000;
+
+/// Warmup used when a grant doesn't specify a curve: 25% rising linearly to 100%.
+pub const DEFAULT_WARMUP_CURVE: WarmupCurve = WarmupCurve {
+    start_bps: 2_500,
+    shape: WarmupShape::Linear,
+};
+
 #[contract]
 pub struct GrantContract;
 
@@ -48,42 +60,318 @@ pub enum GrantStatus {
     Cancelled,
 }
 
-/// 90 days in seconds (inactivity threshold for slash_inactive_grant).
+/// 90 days in seconds (default inactivity threshold for slash_inactive_grant).
 const INACTIVITY_THRESHOLD_SECS: u64 = 90 * 24 * 60 * 60; // 7_776_000
 
+/// 7 days in seconds (default grace window between flag_inactive and slash_inactive_grant).
+const SLASH_GRACE_PERIOD_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// A timelocked rate change waiting to take effect, as returned by `pending_rate_change`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingRateChange {
+    pub current_rate: i128,
+    pub pending_rate: i128,
+    pub effective_timestamp: u64,
+    pub seconds_remaining: u64,
+    pub justification: Option<BytesN<32>>,
+}
+
+/// From `start` on, the grant flows at `rate` (scaled like `flow_rate`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RateSegment {
+    pub start: u64,
+    pub rate: i128,
+}
+
+/// Keeper reward for slash_inactive_grant or process_payouts: `bps` of the amount handled,
+/// at most `cap`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct KeeperBounty {
+    pub bps: u32,
+    pub cap: i128,
+}
+
+/// Dispute state of a grant. While a dispute is open, withdrawals and rate changes are frozen.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[contracttype]
+pub struct Dispute {
+    /// Who opened the dispute; None when the grant has no open dispute.
+    pub opened_by: Option<Address>,
+    pub opened_at: u64,
+    /// Optional hash of the off-chain statement of the dispute.
+    pub reason: Option<BytesN<32>>,
+}
+
+/// Whether payouts from a grant call `on_grant_payment(grant_id, amount)` on the contract paid.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum PaymentHook {
+    Off,
+    /// A failing hook is ignored and the withdrawal goes through.
+    BestEffort,
+    /// A failing hook reverts the withdrawal.
+    Required,
+}
+
+/// A co-recipient of a split grant and their share of everything it accrues.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RecipientShare {
+    pub recipient: Address,
+    pub share_bps: u32,
+}
+
+/// A clawback of accrued funds proposed by the admin, executable once its timelock passes.
+/// The amount is held back from the grantee's withdrawals in the meantime.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingClawback {
+    /// 0 when no clawback is pending.
+    pub amount: i128,
+    /// Why the funds are being reclaimed, as defined by the DAO's published code list.
+    pub reason_code: u32,
+    pub executable_at: u64,
+}
+
+/// How an arbitrator closes a dispute.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum DisputeResolution {
+    /// Lift the freeze; the grant continues as before.
+    Resume,
+    /// Cancel the grant. Whatever had accrued stays claimable by the grantee.
+    Cancel,
+    /// Cancel the grant and split everything not yet withdrawn: the given share in basis
+    /// points is paid to the grantee, the rest to the treasury.
+    Split(u32),
+}
+
+/// How the rate multiplier rises from `start_bps` to 100% over the warmup period.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum WarmupShape {
+    Linear,
+    /// Holds at `start_bps` for the whole warmup, then jumps to 100%.
+    Step,
+    /// Quadratic ease-in: slow at first, fastest near the end of the warmup.
+    Quadratic,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WarmupCurve {
+    pub start_bps: u32,
+    pub shape: WarmupShape,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub struct Grant {
     pub recipient: Address,
+    /// Token this grant is denominated and paid out in.
+    pub token: Address,
     pub total_amount: i128,
+    /// Tokens actually received by the contract for this grant via fund_grant. Transfer fees
+    /// come out of total_amount, so a funded grant never pays out more than arrived.
+    pub funded: i128,
     pub withdrawn: i128,
     pub claimable: i128,
     pub flow_rate: i128,
     pub last_update_ts: u64,
     pub rate_updated_at: u64,
-    /// Last time the grantee withdrew (or grant creation if never claimed). Used for inactivity slash.
+    /// Last time the grantee withdrew or sent a heartbeat (or grant creation). Used for inactivity slash.
     pub last_claim_time: u64,
+    /// Seconds without activity before the grant can be slashed; 0 uses the contract default.
+    pub inactivity_threshold: u64,
+    /// Share of the remaining balance taken by an inactivity slash, in basis points.
+    pub slash_bps: u32,
+    /// When the grant was flagged inactive (0 = not flagged). Cleared by grantee activity.
+    pub inactive_flagged_at: u64,
+    /// Open dispute, if any. Cleared by resolve_dispute.
+    pub dispute: Dispute,
+    /// Clawback waiting for its timelock, if any. Its amount can't be withdrawn meanwhile.
+    pub pending_clawback: PendingClawback,
+    /// Co-recipients of a split grant, summing to 100%. Empty when `recipient` gets everything.
+    pub shares: Vec<RecipientShare>,
+    /// Each co-recipient's unwithdrawn balance as of `shares_accrued_base`. Past co-recipients
+    /// keep their entry until it is withdrawn.
+    pub share_balances: Map<Address, i128>,
+    /// Total accrued (withdrawn + claimable) when share balances were last brought up to date.
+    pub shares_accrued_base: i128,
+    /// Grant this one was carved out of by create_subgrant, if any.
+    pub parent_id: Option<u64>,
+    /// Sub-grants carved out of this grant. They are cancelled along with it.
+    pub subgrants: Vec<u64>,
+    /// Address allowed to trigger withdrawals to `recipient` on their behalf.
+    pub claim_delegate: Option<Address>,
+    /// Seconds between automatic payouts pushed by process_payouts (0 = off).
+    pub payout_interval: u64,
+    /// When process_payouts last paid this grant out (or payouts were turned on).
+    pub last_payout_at: u64,
+    /// Whether the recipient may sell or pledge the grant with transfer_position.
+    pub transferable: bool,
+    /// Callback made to the payee after each withdrawal.
+    pub payment_hook: PaymentHook,
+    /// Program this grant was created under, if any. Its total_amount counts against the
+    /// program's budget.
+    pub program_id: Option<u64>,
+    /// Whether cancel_grant may revoke the grant.
+    pub revocable: bool,
     pub pending_rate: i128,
     pub effective_timestamp: u64,
+    /// Optional hash of the off-chain justification for the pending rate change.
+    pub pending_justification: Option<BytesN<32>>,
+    /// Upcoming (start_timestamp, rate) segments set at creation, ordered by start.
+    /// Each is applied and removed by settle_grant once its start has passed.
+    pub rate_schedule: Vec<RateSegment>,
+    /// Accrual below one whole token carried between settlements, in 1 / ACCRUAL_PRECISION units.
+    pub accrual_remainder: i128,
     pub status: GrantStatus,
     pub start_time: u64,
     pub warmup_duration: u64,
+    /// Share of `flow_rate` paid at the start of the warmup, in basis points.
+    pub warmup_start_bps: u32,
+    pub warmup_shape: WarmupShape,
+}
+
+/// A quadratic funding round. Contributions go straight to projects; at close, the
+/// matching pool is split quadratically and each project's match is streamed as a grant.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct QfRound {
+    pub token: Address,
+    /// Matching funds held by the contract; the most the round can pay out in matches.
+    pub matching_pool: i128,
+    pub start_time: u64,
+    pub end_time: u64,
+    /// Seconds over which each project's match is streamed after the round closes.
+    pub stream_duration: u64,
+    pub projects: Vec<Address>,
+    pub closed: bool,
+}
+
+/// A project's result from close_qf_round.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct QfMatch {
+    pub project: Address,
+    pub contributions: i128,
+    pub match_amount: i128,
+    /// Grant streaming the match, if the project was matched at all.
+    pub grant_id: Option<u64>,
+}
+
+/// Parameters applied to every grant created in a program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ProgramDefaults {
+    pub warmup_duration: u64,
+    pub warmup_curve: WarmupCurve,
+    /// 0 uses the contract-wide default.
+    pub inactivity_threshold: u64,
+    pub revocable: bool,
+}
+
+/// A funding round grouping grants under one budget, paid in one token.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Program {
+    pub name: String,
+    /// May create grants in the program.
+    pub manager: Address,
+    pub token: Address,
+    /// Cap on the sum of total_amount over the program's grants.
+    pub budget: i128,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub defaults: ProgramDefaults,
+}
+
+/// Arguments of one `create_grant` call, for batch_create_grants.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct GrantParams {
+    pub grant_id: u64,
+    pub recipient: Address,
+    pub token: Address,
+    pub total_amount: i128,
+    pub flow_rate: i128,
+    pub warmup_duration: u64,
+    /// DEFAULT_WARMUP_CURVE is what create_grant uses when given no curve.
+    pub warmup_curve: WarmupCurve,
+    pub rate_schedule: Option<Vec<RateSegment>>,
+}
+
+/// Period a human-friendly rate is expressed over. Months are 30 days and years 365 days.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum RatePeriod {
+    Second,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl RatePeriod {
+    fn seconds(self) -> i128 {
+        match self {
+            RatePeriod::Second => 1,
+            RatePeriod::Hour => 60 * 60,
+            RatePeriod::Day => 24 * 60 * 60,
+            RatePeriod::Week => 7 * 24 * 60 * 60,
+            RatePeriod::Month => 30 * 24 * 60 * 60,
+            RatePeriod::Year => 365 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Who bears the fee when a fee-on-transfer token pays out less than was sent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum FeePolicy {
+    /// The recipient receives `amount - fee`; the grant is debited the full amount.
+    Recipient,
+    /// The grant is debited only what the recipient received; the fee comes out of
+    /// the contract's unallocated reserve.
+    Contract,
 }
 
 #[derive(Clone)]
 #[contracttype]
 enum DataKey {
     Admin,
-    /// Token used for grants; allocated funds are measured in this token.
-    GrantToken,
     /// All grant IDs ever created (for computing total_allocated_funds).
     GrantIds,
     /// DAO treasury; slashed funds are sent here.
     Treasury,
-    /// All grant IDs ever created (for computing total_allocated_funds).
-    GrantIds,
     Oracle,
     Grant(u64),
+    /// Grant IDs owned by a recipient (for withdraw_all).
+    RecipientGrants(Address),
+    FeePolicy,
+    /// Notice period before a rate decrease applies (0 = immediately).
+    RateDecreaseNotice,
+    /// Inactivity threshold for grants that don't set their own.
+    DefaultInactivityThreshold,
+    /// Grace window after flag_inactive before the grant can be slashed.
+    SlashGracePeriod,
+    /// Bounty paid to slash_inactive_grant callers, per token.
+    KeeperBounty(Address),
+    /// Addresses allowed to resolve disputes.
+    Arbitrators,
+    /// Fee paid to process_payouts callers out of each payout, per token.
+    PayoutFee(Address),
+    Program(u64),
+    QfRound(u64),
+    /// IDs of QF rounds not closed yet, whose matching pools the contract holds.
+    OpenQfRounds,
+    /// Amount each contributor gave to a project in a quadratic funding round.
+    QfContributions(u64, Address),
 }
 
 #[contracterror]
@@ -101,15 +389,88 @@ pub enum Error {
     MathOverflow = 9,
     /// Rescue amount would leave less than total allocated funds in the contract.
     RescueWouldViolateAllocated = 10,
-    GranteeMismatch = 10,
-    /// Rescue amount would leave less than total allocated funds in the contract.
-    RescueWouldViolateAllocated = 10,
     /// Grant has been active (claimed) within the inactivity threshold; cannot slash yet.
     GrantNotInactive = 11,
+    /// The recipient has nothing to withdraw across their grants.
+    NothingToWithdraw = 12,
+    /// The contract's balance of a token would fall below its allocated funds.
+    InsufficientReserve = 13,
+    /// Warmup start share is above 100%.
+    InvalidWarmup = 14,
+    /// Rate schedule segments must start in the future, in increasing order, with non-negative rates.
+    InvalidSchedule = 15,
+    /// The grant has no timelocked rate change (of the required kind) waiting to take effect.
+    NoPendingRateChange = 16,
+    /// slash_inactive_grant needs the grant to be flagged by flag_inactive first.
+    NotFlaggedInactive = 17,
+    /// The grantee still has time to act before a flagged grant can be slashed.
+    GracePeriodActive = 18,
+    /// The grant is already flagged inactive.
+    AlreadyFlaggedInactive = 19,
+    /// The grant has an open dispute; withdrawals and rate changes are frozen.
+    UnderDispute = 20,
+    /// The grant has no open dispute to resolve.
+    NotUnderDispute = 21,
+    /// The grant already has a clawback waiting for its timelock.
+    ClawbackPending = 22,
+    /// The grant has no pending clawback.
+    NoPendingClawback = 23,
+    /// The clawback's timelock has not passed yet.
+    ClawbackTimelockActive = 24,
+    /// The cancelled grant's unaccrued remainder has already been returned.
+    NothingToFinalize = 25,
+    /// The grant pays several recipients; each withdraws with withdraw_share.
+    SplitGrant = 26,
+    /// Shares must be positive, name each recipient once and sum to 100%.
+    InvalidShares = 27,
+    /// The admin has marked the grant's position as non-transferable.
+    NotTransferable = 28,
+    /// The payee's required on_grant_payment hook failed.
+    PaymentHookFailed = 29,
+    ProgramNotFound = 30,
+    ProgramAlreadyExists = 31,
+    /// The program's grants would add up to more than its budget.
+    BudgetExceeded = 32,
+    /// The program is outside its start/end dates.
+    ProgramNotOpen = 33,
+    /// The grant was created as non-revocable and can't be cancelled.
+    NotRevocable = 34,
+    QfRoundNotFound = 35,
+    QfRoundAlreadyExists = 36,
+    /// The action needs the round to be in (or, for closing, past) its contribution window.
+    QfRoundNotOpen = 37,
+    QfRoundClosed = 38,
+    /// The address is not a project of the round.
+    UnknownProject = 39,
+    /// The caller is not the grant's current recipient.
+    GranteeMismatch = 40,
+    /// The token rejected the transfer to the payee.
+    TransferFailed = 41,
+}
+
+/// Amount withdrawn from a single grant by `withdraw_all` or `process_payouts`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct GrantWithdrawal {
+    pub grant_id: u64,
+    pub token: Address,
+    pub amount: i128,
 }
 
 const RATE_INCREASE_TIMELOCK_SECS: u64 = 48 * 60 * 60;
 
+/// Fixed-point scale for square roots in quadratic funding matches.
+const QF_SQRT_PRECISION: i128 = 1_000_000;
+
+/// QF contributions live in persistent storage, as a round can collect many of them.
+/// Each contribution extends their entry's TTL to about 30 days (at ~5s per ledger)
+/// once it drops below about 15.
+const QF_CONTRIBUTIONS_TTL_LEDGERS: u32 = 30 * 17_280;
+const QF_CONTRIBUTIONS_TTL_THRESHOLD: u32 = 15 * 17_280;
+
+/// Delay between proposing a clawback and being able to execute it.
+const CLAWBACK_TIMELOCK_SECS: u64 = 48 * 60 * 60;
+
 fn read_admin(env: &Env) -> Result<Address, Error> {
     env.storage()
         .instance()
@@ -147,13 +508,6 @@ fn write_grant(env: &Env, grant_id: u64, grant: &Grant) {
     env.storage().instance().set(&DataKey::Grant(grant_id), grant);
 }
 
-fn read_grant_token(env: &Env) -> Result<Address, Error> {
-    env.storage()
-        .instance()
-        .get(&DataKey::GrantToken)
-        .ok_or(Error::NotInitialized)
-}
-
 fn read_treasury(env: &Env) -> Result<Address, Error> {
     env.storage()
         .instance()
@@ -168,14 +522,150 @@ fn read_grant_ids(env: &Env) -> Vec<u64> {
         .unwrap_or_else(|| Vec::new(env))
 }
 
-/// Sum of (total_amount - withdrawn) for all active grants. Represents tokens that must remain in the contract.
-fn total_allocated_funds(env: &Env) -> Result<i128, Error> {
+/// Base units per whole token, read from the token's `decimals()`.
+fn token_unit(env: &Env, token: &Address) -> Result<i128, Error> {
+    let decimals = token::Client::new(env, token).decimals();
+    10_i128.checked_pow(decimals).ok_or(Error::MathOverflow)
+}
+
+/// Converts `amount` tokens per `period` into a scaled per-second flow rate, rounded down.
+/// `amount` is a fixed-point number with `amount_decimals` fractional digits, so 1_250
+/// with 2 decimals means 12.5 whole tokens.
+fn flow_rate_from_period(
+    env: &Env,
+    token: &Address,
+    amount: i128,
+    amount_decimals: u32,
+    period: RatePeriod,
+) -> Result<i128, Error> {
+    if amount <= 0 {
+        return Err(Error::InvalidRate);
+    }
+
+    let divisor = 10_i128
+        .checked_pow(amount_decimals)
+        .ok_or(Error::MathOverflow)?
+        .checked_mul(period.seconds())
+        .ok_or(Error::MathOverflow)?;
+    amount
+        .checked_mul(token_unit(env, token)?)
+        .ok_or(Error::MathOverflow)?
+        .checked_mul(SCALING_FACTOR)
+        .ok_or(Error::MathOverflow)?
+        .checked_div(divisor)
+        .ok_or(Error::MathOverflow)
+}
+
+/// Converts a scaled per-second flow rate back into tokens per `period`, as a fixed-point
+/// number with `amount_decimals` fractional digits rounded to the nearest last digit. This
+/// recovers the amount given to flow_rate_from_period for every period up to a month; a
+/// per-second rate can't express every amount per year.
+fn flow_rate_to_period(
+    env: &Env,
+    token: &Address,
+    flow_rate: i128,
+    amount_decimals: u32,
+    period: RatePeriod,
+) -> Result<i128, Error> {
+    let divisor = token_unit(env, token)?
+        .checked_mul(SCALING_FACTOR)
+        .ok_or(Error::MathOverflow)?;
+    flow_rate
+        .checked_mul(period.seconds())
+        .ok_or(Error::MathOverflow)?
+        .checked_mul(10_i128.checked_pow(amount_decimals).ok_or(Error::MathOverflow)?)
+        .ok_or(Error::MathOverflow)?
+        .checked_add(divisor / 2)
+        .ok_or(Error::MathOverflow)?
+        .checked_div(divisor)
+        .ok_or(Error::MathOverflow)
+}
+
+fn read_default_inactivity_threshold(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::DefaultInactivityThreshold)
+        .unwrap_or(INACTIVITY_THRESHOLD_SECS)
+}
+
+fn read_slash_grace_period(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::SlashGracePeriod)
+        .unwrap_or(SLASH_GRACE_PERIOD_SECS)
+}
+
+/// Keeper reward configured under `key` for handling `amount`: its bps share, capped, and
+/// never more than `amount` itself. Zero when nothing is configured.
+fn keeper_reward_for(env: &Env, key: &DataKey, amount: i128) -> Result<i128, Error> {
+    let Some(bounty) = env.storage().instance().get::<_, KeeperBounty>(key) else {
+        return Ok(0);
+    };
+
+    let share = amount
+        .checked_mul(i128::from(bounty.bps))
+        .ok_or(Error::MathOverflow)?
+        / FULL_RATE_BPS;
+    let capped = if share > bounty.cap { bounty.cap } else { share };
+    Ok(if capped > amount { amount } else { capped })
+}
+
+fn inactivity_threshold_for(env: &Env, grant: &Grant) -> u64 {
+    if grant.inactivity_threshold == 0 {
+        read_default_inactivity_threshold(env)
+    } else {
+        grant.inactivity_threshold
+    }
+}
+
+fn read_rate_decrease_notice(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::RateDecreaseNotice)
+        .unwrap_or(0)
+}
+
+fn read_fee_policy(env: &Env) -> FeePolicy {
+    env.storage()
+        .instance()
+        .get(&DataKey::FeePolicy)
+        .unwrap_or(FeePolicy::Recipient)
+}
+
+fn read_recipient_grants(env: &Env, recipient: &Address) -> Vec<u64> {
+    env.storage()
+        .instance()
+        .get(&DataKey::RecipientGrants(recipient.clone()))
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+fn add_recipient_grant(env: &Env, recipient: &Address, grant_id: u64) {
+    let mut ids = read_recipient_grants(env, recipient);
+    ids.push_back(grant_id);
+    env.storage()
+        .instance()
+        .set(&DataKey::RecipientGrants(recipient.clone()), &ids);
+}
+
+fn remove_recipient_grant(env: &Env, recipient: &Address, grant_id: u64) {
+    let mut ids = read_recipient_grants(env, recipient);
+    if let Some(index) = ids.first_index_of(grant_id) {
+        ids.remove(index);
+    }
+    env.storage()
+        .instance()
+        .set(&DataKey::RecipientGrants(recipient.clone()), &ids);
+}
+
+/// Sum of (total_amount - withdrawn) for all grants paid in `token`, plus the matching pools of open QF rounds. Represents tokens that must remain in the contract.
+/// Cancelled grants count until finalize_cancelled returns their unaccrued remainder and the grantee withdraws.
+fn total_allocated_funds(env: &Env, token: &Address) -> Result<i128, Error> {
     let mut total = 0_i128;
     let ids = read_grant_ids(env);
     for i in 0..ids.len() {
         let grant_id = ids.get(i).unwrap();
         if let Some(grant) = env.storage().instance().get::<_, Grant>(&DataKey::Grant(grant_id)) {
-            if grant.status == GrantStatus::Active {
+            if grant.token == *token {
                 let remaining = grant
                     .total_amount
                     .checked_sub(grant.withdrawn)
@@ -184,36 +674,125 @@ fn total_allocated_funds(env: &Env) -> Result<i128, Error> {
             }
         }
     }
-    Ok(total)
-    env.storage()
-        .instance()
-        .set(&DataKey::Grant(grant_id), grant);
+    total
+        .checked_add(open_qf_pools(env, token)?)
+        .ok_or(Error::MathOverflow)
 }
 
+/// Transfers `amount` of `token` between two addresses and returns the amount that
+/// actually arrived, measured as the change in the receiver's balance.
+fn transfer_measured(
+    env: &Env,
+    token: &Address,
+    from: &Address,
+    to: &Address,
+    amount: i128,
+) -> Result<i128, Error> {
+    let client = token::Client::new(env, token);
+    let before = client.balance(to);
+    client.transfer(from, to, &amount);
+    let after = client.balance(to);
+    after.checked_sub(before).ok_or(Error::MathOverflow)
+}
 
+/// Same as transfer_measured, but a failed transfer returns None instead of aborting the call.
+fn try_transfer_measured(
+    env: &Env,
+    token: &Address,
+    from: &Address,
+    to: &Address,
+    amount: i128,
+) -> Option<i128> {
+    let client = token::Client::new(env, token);
+    let before = client.balance(to);
+    if !matches!(client.try_transfer(from, to, &amount), Ok(Ok(()))) {
+        return None;
+    }
+    client.balance(to).checked_sub(before)
+}
 
+/// Whether the contract holds enough `token` beyond what its grants and QF pools are owed
+/// to cover `shortfall`. Grants count in full whether or not they were funded through
+/// fund_grant, so one grant's tokens never pay another grant's transfer fees.
+fn reserve_covers(env: &Env, token: &Address, shortfall: i128) -> Result<bool, Error> {
+    let balance = token::Client::new(env, token).balance(&env.current_contract_address());
+    let needed = total_allocated_funds(env, token)?
+        .checked_add(shortfall)
+        .ok_or(Error::MathOverflow)?;
+    Ok(balance >= needed)
+}
 
-fn calculate_warmup_multiplier(grant: &Grant, now: u64) -> i128 {
-    if grant.warmup_duration == 0 {
-        return 10000; // 100% in basis points
+/// Cumulative warmup-weighted time from grant start to `t` in bps-seconds, returned as
+/// (numerator, denominator) so that curved warmups can be integrated without rounding.
+fn warmup_weight(grant: &Grant, t: u64) -> Result<(i128, i128), Error> {
+    let elapsed = i128::from(t.saturating_sub(grant.start_time));
+    let duration = i128::from(grant.warmup_duration);
+    if duration == 0 {
+        let num = FULL_RATE_BPS.checked_mul(elapsed).ok_or(Error::MathOverflow)?;
+        return Ok((num, 1));
     }
 
-    let warmup_end = grant.start_time + grant.warmup_duration;
-    
-    if now >= warmup_end {
-        return 10000; // 100% after warmup period
-    }
+    let x = if elapsed < duration { elapsed } else { duration };
+    let post = elapsed - x;
+    let start_bps = i128::from(grant.warmup_start_bps);
+    let ramp_bps = FULL_RATE_BPS - start_bps;
+
+    // Multiplier at progress p = x / duration:
+    //   Step:      start
+    //   Linear:    start + ramp * p
+    //   Quadratic: start + ramp * p^2
+    // and the integral over [0, x] is start * x + ramp * x^(n+1) / ((n+1) * duration^n).
+    let (curve_num, den) = match grant.warmup_shape {
+        WarmupShape::Step => (0, 1),
+        WarmupShape::Linear => {
+            let x2 = x.checked_mul(x).ok_or(Error::MathOverflow)?;
+            (x2, duration.checked_mul(2).ok_or(Error::MathOverflow)?)
+        }
+        WarmupShape::Quadratic => {
+            let x3 = x
+                .checked_mul(x)
+                .and_then(|v| v.checked_mul(x))
+                .ok_or(Error::MathOverflow)?;
+            let den = duration
+                .checked_mul(duration)
+                .and_then(|v| v.checked_mul(3))
+                .ok_or(Error::MathOverflow)?;
+            (x3, den)
+        }
+    };
 
-    if now <= grant.start_time {
-        return 2500; // 25% at start
-    }
+    let flat_bps_secs = start_bps
+        .checked_mul(x)
+        .and_then(|v| v.checked_add(FULL_RATE_BPS.checked_mul(post)?))
+        .ok_or(Error::MathOverflow)?;
+    let num = flat_bps_secs
+        .checked_mul(den)
+        .and_then(|v| v.checked_add(ramp_bps.checked_mul(curve_num)?))
+        .ok_or(Error::MathOverflow)?;
+
+    Ok((num, den))
+}
 
-    // Linear interpolation from 25% to 100% over warmup_duration
-    let elapsed_warmup = now - grant.start_time;
-    let progress = (elapsed_warmup as i128 * 10000) / (grant.warmup_duration as i128);
-    
-    // 25% + (75% * progress)
-    2500 + (7500 * progress / 10000)
+/// `floor(rate * warmup_weight(t))`, in 1 / ACCRUAL_PRECISION token units.
+fn cumulative_accrual(grant: &Grant, rate: i128, t: u64) -> Result<i128, Error> {
+    let (num, den) = warmup_weight(grant, t)?;
+    // Split num / den into quotient and remainder to keep rate * num from overflowing.
+    let whole = rate
+        .checked_mul(num / den)
+        .ok_or(Error::MathOverflow)?;
+    let part = rate
+        .checked_mul(num % den)
+        .ok_or(Error::MathOverflow)?
+        / den;
+    whole.checked_add(part).ok_or(Error::MathOverflow)
+}
+
+/// Accrual at a constant `rate` over [from, to], weighted by the warmup curve.
+/// Differences of the cumulative floor telescope, so splitting an interval is exact.
+fn weighted_accrual(grant: &Grant, rate: i128, from: u64, to: u64) -> Result<i128, Error> {
+    cumulative_accrual(grant, rate, to)?
+        .checked_sub(cumulative_accrual(grant, rate, from)?)
+        .ok_or(Error::MathOverflow)
 }
 
 fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
@@ -239,66 +818,62 @@ fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
     let mut accrued: i128 = 0;
     let mut cursor = start;
 
-    let has_pending_increase =
-        grant.pending_rate > grant.flow_rate && grant.effective_timestamp != 0;
-    if has_pending_increase {
-        let activation_ts = grant.effective_timestamp;
+    // Walk every rate change that takes effect by `now` (a timelocked proposal or the
+    // next scheduled segment), accruing each piece at the rate in force on it.
+    loop {
+        let pending_ts = if grant.effective_timestamp != 0 {
+            Some(grant.effective_timestamp)
+        } else {
+            None
+        };
+        let segment_ts = grant.rate_schedule.first().map(|segment| segment.start);
+        let activation_ts = match (pending_ts, segment_ts) {
+            (Some(pending), Some(segment)) if segment < pending => segment,
+            (Some(pending), _) => pending,
+            (None, Some(segment)) => segment,
+            (None, None) => break,
+        };
+        if activation_ts > now {
+            break;
+        }
 
         if cursor < activation_ts {
-            let pre_end = if now < activation_ts {
-                now
-            } else {
-                activation_ts
-            };
-            let pre_elapsed = pre_end - cursor;
-            let pre_accrued = grant
-                .flow_rate
-                .checked_mul(i128::from(pre_elapsed))
-                .ok_or(Error::MathOverflow)?;
+            let pre_accrued = weighted_accrual(grant, grant.flow_rate, cursor, activation_ts)?;
             accrued = accrued
                 .checked_add(pre_accrued)
                 .ok_or(Error::MathOverflow)?;
-            cursor = pre_end;
+            cursor = activation_ts;
         }
 
-        if now >= activation_ts {
+        if pending_ts == Some(activation_ts) {
             grant.flow_rate = grant.pending_rate;
-            grant.rate_updated_at = activation_ts;
             grant.pending_rate = 0;
             grant.effective_timestamp = 0;
+            grant.pending_justification = None;
+        } else {
+            grant.flow_rate = grant.rate_schedule.pop_front_unchecked().rate;
         }
+        grant.rate_updated_at = activation_ts;
     }
 
     if cursor < now {
-        let post_elapsed = now - cursor;
-        let post_accrued = grant
-            .flow_rate
-            .checked_mul(i128::from(post_elapsed))
-            .ok_or(Error::MathOverflow)?;
+        let post_accrued = weighted_accrual(grant, grant.flow_rate, cursor, now)?;
         accrued = accrued
             .checked_add(post_accrued)
             .ok_or(Error::MathOverflow)?;
     }
-    let elapsed_i128 = i128::from(elapsed);
-    
-    // Calculate accrued amount with warmup multiplier
-    let base_accrued = grant
-    // Flow rate is stored as a scaled value, so we divide by SCALING_FACTOR
-    // to get the actual accrued amount in token units
-    let scaled_accrued = grant
-        .flow_rate
-        .checked_mul(elapsed_i128)
+
+    // Flow rate is stored as a scaled value and the warmup weight is in basis points,
+    // so `accrued` is in units of 1 / (SCALING_FACTOR * 10000) tokens. Whatever
+    // doesn't make a whole token is carried to the next settlement.
+    let precise = accrued
+        .checked_add(grant.accrual_remainder)
         .ok_or(Error::MathOverflow)?;
-    let accrued = scaled_accrued
-        .checked_div(SCALING_FACTOR)
+    let accrued = precise
+        .checked_div(ACCRUAL_PRECISION)
         .ok_or(Error::MathOverflow)?;
-
-    // Apply warmup multiplier if within warmup period
-    let multiplier = calculate_warmup_multiplier(grant, now);
-    let accrued = base_accrued
-        .checked_mul(multiplier)
-        .ok_or(Error::MathOverflow)?
-        .checked_div(10000)
+    let carry = precise
+        .checked_rem(ACCRUAL_PRECISION)
         .ok_or(Error::MathOverflow)?;
 
     let accounted = grant
@@ -315,16 +890,17 @@ fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
         .checked_sub(accounted)
         .ok_or(Error::MathOverflow)?;
 
-    let delta = if accrued > remaining {
-        remaining
+    let (delta, carry) = if accrued >= remaining {
+        (remaining, 0)
     } else {
-        accrued
+        (accrued, carry)
     };
 
     grant.claimable = grant
         .claimable
         .checked_add(delta)
         .ok_or(Error::MathOverflow)?;
+    grant.accrual_remainder = carry;
 
     let new_accounted = grant
         .withdrawn
@@ -340,271 +916,2703 @@ fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
     Ok(())
 }
 
+fn validate_rate_schedule(schedule: &Vec<RateSegment>, now: u64) -> Result<(), Error> {
+    let mut previous_start = now;
+    for segment in schedule.iter() {
+        if segment.start <= previous_start || segment.rate < 0 {
+            return Err(Error::InvalidSchedule);
+        }
+        previous_start = segment.start;
+    }
+    Ok(())
+}
+
+/// settle_grant for state-changing calls: also emits `rateeff` when a timelocked
+/// rate change (increase or noticed decrease) takes effect during the settlement.
+fn settle_grant_and_notify(
+    env: &Env,
+    grant_id: u64,
+    grant: &mut Grant,
+    now: u64,
+) -> Result<(), Error> {
+    let old_rate = grant.flow_rate;
+    let pending_rate = grant.pending_rate;
+    let effective_timestamp = grant.effective_timestamp;
+
+    settle_grant(grant, now)?;
+
+    if effective_timestamp != 0 && grant.effective_timestamp == 0 {
+        env.events().publish(
+            (symbol_short!("rateeff"), grant_id),
+            (old_rate, pending_rate, effective_timestamp),
+        );
+    }
+    Ok(())
+}
+
 fn preview_grant_at_now(env: &Env, grant: &Grant) -> Result<Grant, Error> {
     let mut preview = grant.clone();
     settle_grant(&mut preview, env.ledger().timestamp())?;
     Ok(preview)
 }
 
-#[contractimpl]
-impl GrantContract {
-    pub fn initialize(env: Env, admin: Address, grant_token: Address) -> Result<(), Error> {
-    pub fn initialize(
-        env: Env,
-        admin: Address,
-        grant_token: Address,
-        treasury: Address,
-    ) -> Result<(), Error> {
-    pub fn initialize(env: Env, admin: Address, oracle_address: Address) -> Result<(), Error> {
-        if env.storage().instance().has(&DataKey::Admin) {
-            return Err(Error::AlreadyInitialized);
-        }
-        admin.require_auth();
-        env.storage().instance().set(&DataKey::Admin, &admin);
-        env.storage().instance().set(&DataKey::GrantToken, &grant_token);
-        env.storage()
-            .instance()
-            .set(&DataKey::GrantIds, &Vec::<u64>::new(&env));
-        env.storage().instance().set(&DataKey::Treasury, &treasury);
-        env.storage()
-            .instance()
-            .set(&DataKey::GrantIds, &Vec::<u64>::new(&env));
-        env.storage()
-            .instance()
-            .set(&DataKey::Oracle, &oracle_address);
-        Ok(())
+/// Divides `amount` between co-recipients by share. Rounding dust goes to the first share.
+fn split_by_shares(
+    env: &Env,
+    shares: &Vec<RecipientShare>,
+    amount: i128,
+) -> Result<Map<Address, i128>, Error> {
+    let mut parts = Map::new(env);
+    let mut dust = amount;
+    for share in shares.iter() {
+        let part = amount
+            .checked_mul(i128::from(share.share_bps))
+            .ok_or(Error::MathOverflow)?
+            / FULL_RATE_BPS;
+        dust = dust.checked_sub(part).ok_or(Error::MathOverflow)?;
+        parts.set(share.recipient, part);
     }
 
-    pub fn create_grant(
-        env: Env,
-        grant_id: u64,
-        recipient: Address,
-        total_amount: i128,
-        flow_rate: i128,
-        warmup_duration: u64,
-    ) -> Result<(), Error> {
-        require_admin_auth(&env)?;
+    if let Some(first) = shares.first() {
+        let part = parts
+            .get(first.recipient.clone())
+            .unwrap_or(0)
+            .checked_add(dust)
+            .ok_or(Error::MathOverflow)?;
+        parts.set(first.recipient, part);
+    }
+    Ok(parts)
+}
 
-        if total_amount <= 0 {
-            return Err(Error::InvalidAmount);
-        }
+/// Credits each co-recipient with their share of everything accrued since the last call,
+/// on an already settled grant.
+fn update_share_balances(env: &Env, grant: &mut Grant) -> Result<(), Error> {
+    let accrued = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+    let delta = accrued
+        .checked_sub(grant.shares_accrued_base)
+        .ok_or(Error::MathOverflow)?;
+    if delta == 0 || grant.shares.is_empty() {
+        grant.shares_accrued_base = accrued;
+        return Ok(());
+    }
 
-        if flow_rate < 0 {
-            return Err(Error::InvalidRate);
-        }
+    for (recipient, credit) in split_by_shares(env, &grant.shares, delta)?.iter() {
+        let balance = grant
+            .share_balances
+            .get(recipient.clone())
+            .unwrap_or(0)
+            .checked_add(credit)
+            .ok_or(Error::MathOverflow)?;
+        grant.share_balances.set(recipient, balance);
+    }
 
-        let key = DataKey::Grant(grant_id);
-        if env.storage().instance().has(&key) {
-            return Err(Error::GrantAlreadyExists);
-        }
+    grant.shares_accrued_base = accrued;
+    Ok(())
+}
+
+/// Takes `amount`, just removed from the claimable balance of a split grant, out of the
+/// co-recipients' balances in proportion to each balance, so none can go negative. Share
+/// balances must be up to date from before the removal.
+fn debit_share_balances(grant: &mut Grant, amount: i128) -> Result<(), Error> {
+    let mut total = 0_i128;
+    for (_, balance) in grant.share_balances.iter() {
+        total = total.checked_add(balance).ok_or(Error::MathOverflow)?;
+    }
+
+    if total > 0 {
+        let mut left = amount;
+        for (recipient, balance) in grant.share_balances.clone().iter() {
+            let cut = balance.checked_mul(amount).ok_or(Error::MathOverflow)? / total;
+            grant.share_balances.set(recipient, balance - cut);
+            left = left.checked_sub(cut).ok_or(Error::MathOverflow)?;
+        }
+        // Rounding dust comes out of the first balances that can cover it.
+        for (recipient, balance) in grant.share_balances.clone().iter() {
+            if left <= 0 {
+                break;
+            }
+            let cut = if balance < left { balance } else { left };
+            grant.share_balances.set(recipient, balance - cut);
+            left -= cut;
+        }
+    }
+
+    grant.shares_accrued_base = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+    Ok(())
+}
+
+fn validate_shares(shares: &Vec<RecipientShare>) -> Result<(), Error> {
+    let mut total = 0_i128;
+    for (i, share) in shares.iter().enumerate() {
+        if share.share_bps == 0 {
+            return Err(Error::InvalidShares);
+        }
+        for other in shares.iter().skip(i + 1) {
+            if other.recipient == share.recipient {
+                return Err(Error::InvalidShares);
+            }
+        }
+        total += i128::from(share.share_bps);
+    }
+    if total != FULL_RATE_BPS {
+        return Err(Error::InvalidShares);
+    }
+    Ok(())
+}
+
+fn ensure_not_disputed(grant: &Grant) -> Result<(), Error> {
+    if grant.dispute.opened_by.is_some() {
+        return Err(Error::UnderDispute);
+    }
+    Ok(())
+}
+
+fn read_arbitrators(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Arbitrators)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+/// Stops all future accrual on an already settled grant and marks it cancelled.
+fn close_grant(env: &Env, grant: &mut Grant) {
+    grant.flow_rate = 0;
+    grant.pending_rate = 0;
+    grant.effective_timestamp = 0;
+    grant.pending_justification = None;
+    grant.rate_schedule = Vec::new(env);
+    grant.status = GrantStatus::Cancelled;
+}
+
+/// Claimable balance the grantee may withdraw: everything not held for a pending clawback.
+fn withdrawable(grant: &Grant) -> i128 {
+    if grant.claimable > grant.pending_clawback.amount {
+        grant.claimable - grant.pending_clawback.amount
+    } else {
+        0
+    }
+}
+
+/// Cancels the active sub-grants of a grant that is itself being cancelled. Each keeps what
+/// it had accrued; finalize_cancelled returns the rest.
+fn cancel_subgrants(env: &Env, parent: &Grant, now: u64) -> Result<(), Error> {
+    for child_id in parent.subgrants.iter() {
+        let mut child = read_grant(env, child_id)?;
+        if child.status != GrantStatus::Active {
+            continue;
+        }
+        settle_grant_and_notify(env, child_id, &mut child, now)?;
+        close_grant(env, &mut child);
+        // Closing a sub-grant ends any dispute on it, as DisputeResolution::Cancel would.
+        child.dispute = Dispute::default();
+        write_grant(env, child_id, &child);
+    }
+    Ok(())
+}
+
+/// Extends a parent's newly opened dispute to its active sub-grants that have none of their
+/// own, so their withdrawals freeze too.
+fn freeze_subgrants(env: &Env, parent: &Grant, now: u64) -> Result<(), Error> {
+    for child_id in parent.subgrants.iter() {
+        let mut child = read_grant(env, child_id)?;
+        if child.status != GrantStatus::Active || child.dispute.opened_by.is_some() {
+            continue;
+        }
+        settle_grant_and_notify(env, child_id, &mut child, now)?;
+        child.dispute = parent.dispute.clone();
+        write_grant(env, child_id, &child);
+    }
+    Ok(())
+}
+
+/// Lifts `dispute`, resumed on the parent, from the sub-grants it was extended to.
+fn resume_subgrants(env: &Env, parent: &Grant, dispute: &Dispute, now: u64) -> Result<(), Error> {
+    for child_id in parent.subgrants.iter() {
+        let mut child = read_grant(env, child_id)?;
+        if child.dispute != *dispute {
+            continue;
+        }
+        settle_grant_and_notify(env, child_id, &mut child, now)?;
+        lift_dispute(&mut child, now);
+        write_grant(env, child_id, &child);
+    }
+    Ok(())
+}
+
+/// Clears a settled grant's dispute. Time spent frozen doesn't count as grantee
+/// inactivity, nor towards the grace window of an earlier inactivity flag.
+fn lift_dispute(grant: &mut Grant, now: u64) {
+    let frozen_secs = now.saturating_sub(grant.dispute.opened_at);
+    grant.last_claim_time = grant.last_claim_time.saturating_add(frozen_secs).min(now);
+    if grant.inactive_flagged_at != 0 {
+        grant.inactive_flagged_at = grant.inactive_flagged_at.saturating_add(frozen_secs).min(now);
+    }
+    grant.dispute = Dispute::default();
+}
+
+/// Sub-grants are carved out of their parent at a fixed rate, so the parent's rate can't
+/// change while any of them is active.
+fn ensure_no_active_subgrants(env: &Env, grant: &Grant) -> Result<(), Error> {
+    for child_id in grant.subgrants.iter() {
+        if read_grant(env, child_id)?.status == GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+    }
+    Ok(())
+}
+
+/// Moves `amount` from `claimable` to `withdrawn` on an already settled grant.
+fn debit_claimable(grant: &mut Grant, amount: i128, now: u64) -> Result<(), Error> {
+    if amount > withdrawable(grant) {
+        return Err(Error::InvalidAmount);
+    }
+
+    grant.claimable = grant
+        .claimable
+        .checked_sub(amount)
+        .ok_or(Error::MathOverflow)?;
+    grant.withdrawn = grant
+        .withdrawn
+        .checked_add(amount)
+        .ok_or(Error::MathOverflow)?;
+
+    let accounted = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+
+    if accounted > grant.total_amount {
+        return Err(Error::InvalidState);
+    }
+
+    if grant.status == GrantStatus::Active && grant.withdrawn == grant.total_amount {
+        grant.status = GrantStatus::Completed;
+    }
+
+    grant.last_claim_time = now;
+    grant.inactive_flagged_at = 0;
+    Ok(())
+}
+
+/// Credits a grant with `received` out of a `requested` deposit. The transfer fee never
+/// reached the contract, so it comes out of the grant's total rather than other grants' funds.
+fn credit_funding(grant: &mut Grant, requested: i128, received: i128) -> Result<(), Error> {
+    let fee = requested.checked_sub(received).ok_or(Error::MathOverflow)?;
+    grant.total_amount = grant
+        .total_amount
+        .checked_sub(fee)
+        .ok_or(Error::MathOverflow)?;
+    grant.funded = grant
+        .funded
+        .checked_add(received)
+        .ok_or(Error::MathOverflow)?;
+
+    let accounted = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+    if grant.total_amount <= 0
+        || grant.funded > grant.total_amount
+        || accounted > grant.total_amount
+    {
+        return Err(Error::InvalidAmount);
+    }
+    Ok(())
+}
+
+/// Lowers a grant's total after the difference left the grant, taking its funding with it.
+fn lower_total_amount(grant: &mut Grant, total_amount: i128) {
+    grant.total_amount = total_amount;
+    if grant.funded > total_amount {
+        grant.funded = total_amount;
+    }
+}
+
+/// Returns `shortfall` from `withdrawn` back to `claimable` when the contract
+/// absorbs a transfer fee the recipient did not receive.
+fn refund_fee_shortfall(grant: &mut Grant, shortfall: i128) -> Result<(), Error> {
+    grant.withdrawn = grant
+        .withdrawn
+        .checked_sub(shortfall)
+        .ok_or(Error::MathOverflow)?;
+    grant.claimable = grant
+        .claimable
+        .checked_add(shortfall)
+        .ok_or(Error::MathOverflow)?;
+    Ok(())
+}
+
+/// Spreads a transfer fee shortfall for `token` over the grants in a `withdraw_all`
+/// breakdown, starting from the last one, lowering what each is counted as `received`.
+fn spread_fee_shortfall(
+    breakdown: &Vec<GrantWithdrawal>,
+    received: &mut Vec<i128>,
+    token: &Address,
+    shortfall: i128,
+) -> Result<(), Error> {
+    let mut left = shortfall;
+    let mut i = breakdown.len();
+    while i > 0 && left > 0 {
+        i -= 1;
+        if breakdown.get(i).unwrap().token != *token {
+            continue;
+        }
+
+        let amount = received.get(i).unwrap();
+        let fee = if amount < left { amount } else { left };
+        received.set(i, amount.checked_sub(fee).ok_or(Error::MathOverflow)?);
+        left = left.checked_sub(fee).ok_or(Error::MathOverflow)?;
+    }
+    Ok(())
+}
+
+/// Returns the fee each `token` grant in a `withdraw_all` breakdown lost in transfer to
+/// its claimable balance, and lowers its reported amount to what was received.
+fn refund_fee_shortfall_across(
+    env: &Env,
+    breakdown: &mut Vec<GrantWithdrawal>,
+    received: &Vec<i128>,
+    token: &Address,
+) -> Result<(), Error> {
+    for i in 0..breakdown.len() {
+        let mut entry = breakdown.get(i).unwrap();
+        let arrived = received.get(i).unwrap();
+        if entry.token != *token || arrived >= entry.amount {
+            continue;
+        }
+
+        let mut grant = read_grant(env, entry.grant_id)?;
+        refund_fee_shortfall(&mut grant, entry.amount - arrived)?;
+        write_grant(env, entry.grant_id, &grant);
+
+        entry.amount = arrived;
+        breakdown.set(i, entry);
+    }
+    Ok(())
+}
+
+/// Settles a grant, debits `amount` from its claimable balance and pays it to `to`.
+/// `caller` must already be authorized to withdraw from the grant.
+fn withdraw_grant(
+    env: &Env,
+    grant_id: u64,
+    mut grant: Grant,
+    amount: i128,
+    caller: &Address,
+    to: &Address,
+) -> Result<(), Error> {
+    // Cancelled grants stay withdrawable: the grantee keeps what had accrued by then.
+    ensure_not_disputed(&grant)?;
+    if !grant.shares.is_empty() {
+        return Err(Error::SplitGrant);
+    }
+
+    let now = env.ledger().timestamp();
+    settle_grant_and_notify(env, grant_id, &mut grant, now)?;
+    debit_claimable(&mut grant, amount, now)?;
+    write_grant(env, grant_id, &grant);
+
+    let received = pay_out(env, grant_id, &mut grant, to, amount)?;
+
+    env.events().publish(
+        (symbol_short!("withdraw"), grant_id),
+        (caller.clone(), to.clone(), amount, received),
+    );
+
+    call_payment_hook(env, grant.payment_hook, to, grant_id, received)
+}
+
+/// Tells a payee contract about a payment it received, as configured by `hook`.
+fn call_payment_hook(
+    env: &Env,
+    hook: PaymentHook,
+    payee: &Address,
+    grant_id: u64,
+    amount: i128,
+) -> Result<(), Error> {
+    if hook == PaymentHook::Off {
+        return Ok(());
+    }
+
+    let func = Symbol::new(env, "on_grant_payment");
+    let args = vec![env, grant_id.into_val(env), amount.into_val(env)];
+    let result = env.try_invoke_contract::<(), InvokeError>(payee, &func, args);
+    if hook == PaymentHook::Required && !matches!(result, Ok(Ok(()))) {
+        return Err(Error::PaymentHookFailed);
+    }
+    Ok(())
+}
+
+/// Pays `amount` of a grant's token to `to` and applies the fee policy to the grant.
+/// The grant must already have been debited `amount` and written. Returns what `to` received.
+fn pay_out(
+    env: &Env,
+    grant_id: u64,
+    grant: &mut Grant,
+    to: &Address,
+    amount: i128,
+) -> Result<i128, Error> {
+    try_pay_out(env, grant_id, grant, to, amount).ok_or(Error::TransferFailed)
+}
+
+/// Same as pay_out, but returns None instead of aborting the call if the transfer fails,
+/// leaving the grant as written. Under FeePolicy::Contract, a fee the contract's
+/// unallocated balance can't cover is borne by the recipient.
+fn try_pay_out(
+    env: &Env,
+    grant_id: u64,
+    grant: &mut Grant,
+    to: &Address,
+    amount: i128,
+) -> Option<i128> {
+    let contract = env.current_contract_address();
+    let received = try_transfer_measured(env, &grant.token, &contract, to, amount)?;
+
+    let shortfall = amount - received;
+    if shortfall > 0
+        && read_fee_policy(env) == FeePolicy::Contract
+        && matches!(reserve_covers(env, &grant.token, shortfall), Ok(true))
+    {
+        let mut refunded = grant.clone();
+        if refund_fee_shortfall(&mut refunded, shortfall).is_ok() {
+            *grant = refunded;
+            write_grant(env, grant_id, grant);
+        }
+    }
+
+    Some(received)
+}
+
+/// Lowest grant ID above every existing one, for grants the contract creates itself.
+fn next_grant_id(env: &Env) -> Result<u64, Error> {
+    let mut next = 0_u64;
+    for id in read_grant_ids(env).iter() {
+        if id >= next {
+            next = id.checked_add(1).ok_or(Error::MathOverflow)?;
+        }
+    }
+    Ok(next)
+}
+
+fn read_program(env: &Env, program_id: u64) -> Result<Program, Error> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Program(program_id))
+        .ok_or(Error::ProgramNotFound)
+}
+
+/// Sums `f` over the grants created under `program_id`.
+fn sum_program_grants(
+    env: &Env,
+    program_id: u64,
+    f: impl Fn(&Grant) -> Result<i128, Error>,
+) -> Result<i128, Error> {
+    let mut total = 0_i128;
+    let ids = read_grant_ids(env);
+    for i in 0..ids.len() {
+        let grant_id = ids.get(i).unwrap();
+        if let Some(grant) = env.storage().instance().get::<_, Grant>(&DataKey::Grant(grant_id)) {
+            if grant.program_id == Some(program_id) {
+                total = total.checked_add(f(&grant)?).ok_or(Error::MathOverflow)?;
+            }
+        }
+    }
+    Ok(total)
+}
+
+/// Sum of total_amount over a program's grants: how much of its budget is committed.
+fn committed_budget(env: &Env, program_id: u64) -> Result<i128, Error> {
+    sum_program_grants(env, program_id, |grant| Ok(grant.total_amount))
+}
+
+fn read_open_qf_rounds(env: &Env) -> Vec<u64> {
+    env.storage()
+        .instance()
+        .get(&DataKey::OpenQfRounds)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+/// Matching pools in `token` held for QF rounds that haven't closed yet.
+fn open_qf_pools(env: &Env, token: &Address) -> Result<i128, Error> {
+    let mut total = 0_i128;
+    for round_id in read_open_qf_rounds(env).iter() {
+        let round = read_qf_round(env, round_id)?;
+        if round.token == *token {
+            total = total
+                .checked_add(round.matching_pool)
+                .ok_or(Error::MathOverflow)?;
+        }
+    }
+    Ok(total)
+}
+
+fn read_qf_round(env: &Env, round_id: u64) -> Result<QfRound, Error> {
+    env.storage()
+        .instance()
+        .get(&DataKey::QfRound(round_id))
+        .ok_or(Error::QfRoundNotFound)
+}
+
+fn read_qf_contributions(env: &Env, round_id: u64, project: Address) -> Map<Address, i128> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::QfContributions(round_id, project))
+        .unwrap_or_else(|| Map::new(env))
+}
+
+fn write_qf_contributions(
+    env: &Env,
+    round_id: u64,
+    project: Address,
+    contributions: &Map<Address, i128>,
+) {
+    let key = DataKey::QfContributions(round_id, project);
+    env.storage().persistent().set(&key, contributions);
+    env.storage().persistent().extend_ttl(
+        &key,
+        QF_CONTRIBUTIONS_TTL_THRESHOLD,
+        QF_CONTRIBUTIONS_TTL_LEDGERS,
+    );
+}
+
+/// Floor of the square root of `n` (Newton's method on integers).
+fn isqrt(n: i128) -> i128 {
+    if n < 2 {
+        return n.max(0);
+    }
+    let mut x = n;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// Unscaled quadratic match for one project, (sum of sqrt(c))^2 - sum of c, rounded down.
+/// Square roots are taken at QF_SQRT_PRECISION so small contributions keep their weight.
+fn quadratic_match(contributions: &Map<Address, i128>) -> Result<(i128, i128), Error> {
+    let precision_sq = QF_SQRT_PRECISION
+        .checked_mul(QF_SQRT_PRECISION)
+        .ok_or(Error::MathOverflow)?;
+
+    let mut total = 0_i128;
+    let mut sqrt_sum = 0_i128;
+    for (_, amount) in contributions.iter() {
+        total = total.checked_add(amount).ok_or(Error::MathOverflow)?;
+        let scaled = amount.checked_mul(precision_sq).ok_or(Error::MathOverflow)?;
+        sqrt_sum = sqrt_sum.checked_add(isqrt(scaled)).ok_or(Error::MathOverflow)?;
+    }
+
+    let squared = sqrt_sum.checked_mul(sqrt_sum).ok_or(Error::MathOverflow)? / precision_sq;
+    let raw_match = squared.checked_sub(total).ok_or(Error::MathOverflow)?;
+    Ok((total, raw_match.max(0)))
+}
+
+/// Validates and stores a new grant. Shared by create_grant and batch_create_grants;
+/// callers check admin authorization.
+fn insert_grant(env: &Env, params: GrantParams) -> Result<(), Error> {
+    let GrantParams {
+        grant_id,
+        recipient,
+        token,
+        total_amount,
+        flow_rate,
+        warmup_duration,
+        warmup_curve,
+        rate_schedule,
+    } = params;
+
+    if total_amount <= 0 {
+        return Err(Error::InvalidAmount);
+    }
+
+    if flow_rate < 0 {
+        return Err(Error::InvalidRate);
+    }
+
+    if i128::from(warmup_curve.start_bps) > FULL_RATE_BPS {
+        return Err(Error::InvalidWarmup);
+    }
+
+    let key = DataKey::Grant(grant_id);
+    if env.storage().instance().has(&key) {
+        return Err(Error::GrantAlreadyExists);
+    }
+
+    let now = env.ledger().timestamp();
+    let rate_schedule = rate_schedule.unwrap_or_else(|| Vec::new(env));
+    validate_rate_schedule(&rate_schedule, now)?;
+
+    let grant = Grant {
+        recipient: recipient.clone(),
+        token,
+        total_amount,
+        funded: 0,
+        withdrawn: 0,
+        claimable: 0,
+        flow_rate,
+        last_update_ts: now,
+        rate_updated_at: now,
+        last_claim_time: now,
+        inactivity_threshold: 0,
+        slash_bps: FULL_RATE_BPS as u32,
+        inactive_flagged_at: 0,
+        dispute: Dispute::default(),
+        pending_clawback: PendingClawback::default(),
+        shares: Vec::new(env),
+        share_balances: Map::new(env),
+        shares_accrued_base: 0,
+        parent_id: None,
+        subgrants: Vec::new(env),
+        claim_delegate: None,
+        payout_interval: 0,
+        last_payout_at: 0,
+        transferable: true,
+        payment_hook: PaymentHook::Off,
+        program_id: None,
+        revocable: true,
+        pending_rate: 0,
+        effective_timestamp: 0,
+        pending_justification: None,
+        rate_schedule,
+        accrual_remainder: 0,
+        status: GrantStatus::Active,
+        start_time: now,
+        warmup_duration,
+        warmup_start_bps: warmup_curve.start_bps,
+        warmup_shape: warmup_curve.shape,
+    };
+
+    env.storage().instance().set(&key, &grant);
+    add_recipient_grant(env, &recipient, grant_id);
+    let mut ids = read_grant_ids(env);
+    ids.push_back(grant_id);
+    env.storage().instance().set(&DataKey::GrantIds, &ids);
+    Ok(())
+}
+
+/// Settles and cancels an active grant along with its sub-grants. Shared by cancel_grant
+/// and batch_cancel; callers check admin authorization.
+fn cancel_active_grant(env: &Env, grant_id: u64) -> Result<(), Error> {
+    let mut grant = read_grant(env, grant_id)?;
+
+    if grant.status != GrantStatus::Active {
+        return Err(Error::InvalidState);
+    }
+    // A grantee's dispute doesn't shield the grant from cancellation, but one the admin
+    // opened can only be closed through resolve_dispute.
+    if grant.dispute.opened_by == Some(read_admin(env)?) {
+        return Err(Error::UnderDispute);
+    }
+    if !grant.revocable {
+        return Err(Error::NotRevocable);
+    }
+
+    let now = env.ledger().timestamp();
+    settle_grant_and_notify(env, grant_id, &mut grant, now)?;
+    close_grant(env, &mut grant);
+    grant.dispute = Dispute::default();
+    write_grant(env, grant_id, &grant);
+    cancel_subgrants(env, &grant, now)?;
+
+    Ok(())
+}
+
+/// Changes a grant's rate: increases wait for the timelock and decreases for the notice
+/// period. Shared by propose_rate_change and batch_update_rate; callers check admin
+/// authorization.
+fn change_rate(
+    env: &Env,
+    grant_id: u64,
+    new_rate: i128,
+    justification: Option<BytesN<32>>,
+) -> Result<(), Error> {
+    if new_rate < 0 {
+        return Err(Error::InvalidRate);
+    }
+
+    let mut grant = read_grant(env, grant_id)?;
+    if grant.status != GrantStatus::Active {
+        return Err(Error::InvalidState);
+    }
+    ensure_not_disputed(&grant)?;
+    ensure_no_active_subgrants(env, &grant)?;
+
+    let now = env.ledger().timestamp();
+    settle_grant_and_notify(env, grant_id, &mut grant, now)?;
+
+    if grant.status != GrantStatus::Active {
+        write_grant(env, grant_id, &grant);
+        return Err(Error::InvalidState);
+    }
+
+    let old_rate = grant.flow_rate;
+    let decrease_notice = read_rate_decrease_notice(env);
+
+    let delay = if new_rate > grant.flow_rate {
+        RATE_INCREASE_TIMELOCK_SECS
+    } else if new_rate < grant.flow_rate {
+        decrease_notice
+    } else {
+        0
+    };
+
+    if delay > 0 {
+        grant.pending_rate = new_rate;
+        grant.effective_timestamp = now.checked_add(delay).ok_or(Error::MathOverflow)?;
+        grant.pending_justification = justification.clone();
+
+        write_grant(env, grant_id, &grant);
+
+        env.events().publish(
+            (symbol_short!("rateprop"), grant_id),
+            (old_rate, new_rate, grant.effective_timestamp, justification),
+        );
+
+        return Ok(());
+    }
+
+    grant.flow_rate = new_rate;
+    grant.rate_updated_at = now;
+    grant.pending_rate = 0;
+    grant.effective_timestamp = 0;
+    grant.pending_justification = None;
+
+    write_grant(env, grant_id, &grant);
+
+    env.events().publish(
+        (symbol_short!("rateupdt"), grant_id),
+        (old_rate, new_rate, grant.rate_updated_at, justification),
+    );
+
+    Ok(())
+}
+
+#[contractimpl]
+impl GrantContract {
+    pub fn initialize(
+        env: Env,
+        admin: Address,
+        treasury: Address,
+        oracle_address: Address,
+    ) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::Treasury, &treasury);
+        env.storage()
+            .instance()
+            .set(&DataKey::GrantIds, &Vec::<u64>::new(&env));
+        env.storage()
+            .instance()
+            .set(&DataKey::Oracle, &oracle_address);
+        Ok(())
+    }
+
+    pub fn create_grant(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        token: Address,
+        total_amount: i128,
+        flow_rate: i128,
+        warmup_duration: u64,
+        warmup_curve: Option<WarmupCurve>,
+        rate_schedule: Option<Vec<RateSegment>>,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        insert_grant(
+            &env,
+            GrantParams {
+                grant_id,
+                recipient,
+                token,
+                total_amount,
+                flow_rate,
+                warmup_duration,
+                warmup_curve: warmup_curve.unwrap_or(DEFAULT_WARMUP_CURVE),
+                rate_schedule,
+            },
+        )
+    }
+
+    /// Parent-recipient-only. Re-stream `rate` (scaled like `flow_rate`) of a grant to
+    /// `recipient` as a new sub-grant, returning its ID. The parent's rate drops by `rate` and
+    /// the sub-grant takes the matching share of the parent's unaccrued balance, so both run
+    /// out together. The sub-grant is cancelled along with its parent and frozen by disputes
+    /// opened on it. The parent's rate can't change while it has active sub-grants.
+    pub fn create_subgrant(
+        env: Env,
+        parent_id: u64,
+        recipient: Address,
+        rate: i128,
+    ) -> Result<u64, Error> {
+        let mut parent = read_grant(&env, parent_id)?;
+        parent.recipient.require_auth();
+
+        if parent.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+        ensure_not_disputed(&parent)?;
+        if !parent.shares.is_empty() {
+            return Err(Error::SplitGrant);
+        }
+
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, parent_id, &mut parent, now)?;
+
+        // Future rate changes of the parent would not carry over to the sub-grant, and
+        // sub-grants don't nest.
+        if parent.effective_timestamp != 0
+            || !parent.rate_schedule.is_empty()
+            || parent.parent_id.is_some()
+        {
+            return Err(Error::InvalidState);
+        }
+
+        if rate <= 0 || rate > parent.flow_rate {
+            return Err(Error::InvalidRate);
+        }
+
+        let accounted = parent
+            .withdrawn
+            .checked_add(parent.claimable)
+            .ok_or(Error::MathOverflow)?;
+        let unaccrued = parent
+            .total_amount
+            .checked_sub(accounted)
+            .ok_or(Error::MathOverflow)?;
+        let total_amount = unaccrued
+            .checked_mul(rate)
+            .ok_or(Error::MathOverflow)?
+            / parent.flow_rate;
+        if total_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let subgrant_id = next_grant_id(&env)?;
+
+        // Funding beyond what the parent's reduced total needs moves to the sub-grant.
+        let parent_total = parent
+            .total_amount
+            .checked_sub(total_amount)
+            .ok_or(Error::MathOverflow)?;
+        let funded = if parent.funded > parent_total {
+            parent.funded - parent_total
+        } else {
+            0
+        };
+
+        let subgrant = Grant {
+            recipient: recipient.clone(),
+            token: parent.token.clone(),
+            total_amount,
+            funded,
+            withdrawn: 0,
+            claimable: 0,
+            flow_rate: rate,
+            last_update_ts: now,
+            rate_updated_at: now,
+            last_claim_time: now,
+            inactivity_threshold: 0,
+            slash_bps: FULL_RATE_BPS as u32,
+            inactive_flagged_at: 0,
+            dispute: Dispute::default(),
+            pending_clawback: PendingClawback::default(),
+            shares: Vec::new(&env),
+            share_balances: Map::new(&env),
+            shares_accrued_base: 0,
+            parent_id: Some(parent_id),
+            subgrants: Vec::new(&env),
+            claim_delegate: None,
+            payout_interval: 0,
+            last_payout_at: 0,
+            transferable: true,
+            payment_hook: PaymentHook::Off,
+            program_id: parent.program_id,
+            revocable: parent.revocable,
+            pending_rate: 0,
+            effective_timestamp: 0,
+            pending_justification: None,
+            rate_schedule: Vec::new(&env),
+            accrual_remainder: 0,
+            status: GrantStatus::Active,
+            // Follow the parent's warmup so the carved-out rate matches what the parent loses.
+            start_time: parent.start_time,
+            warmup_duration: parent.warmup_duration,
+            warmup_start_bps: parent.warmup_start_bps,
+            warmup_shape: parent.warmup_shape,
+        };
+
+        lower_total_amount(&mut parent, parent_total);
+        parent.flow_rate = parent
+            .flow_rate
+            .checked_sub(rate)
+            .ok_or(Error::MathOverflow)?;
+        parent.rate_updated_at = now;
+        parent.subgrants.push_back(subgrant_id);
+        write_grant(&env, parent_id, &parent);
+
+        write_grant(&env, subgrant_id, &subgrant);
+        add_recipient_grant(&env, &recipient, subgrant_id);
+        let mut ids = read_grant_ids(&env);
+        ids.push_back(subgrant_id);
+        env.storage().instance().set(&DataKey::GrantIds, &ids);
+
+        env.events().publish(
+            (symbol_short!("subgrant"), parent_id),
+            (subgrant_id, recipient, rate, total_amount),
+        );
+
+        Ok(subgrant_id)
+    }
+
+    /// Same as `create_grant`, but the rate is given as `amount_per_period` tokens per
+    /// `period` instead of a scaled per-second flow rate. `amount_per_period` is fixed-point
+    /// with `amount_decimals` fractional digits (use 0 for whole tokens); the flow rate is
+    /// derived from the token's decimals. Grants with a rate schedule must be created with
+    /// `create_grant`.
+    pub fn create_grant_with_rate(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        token: Address,
+        total_amount: i128,
+        amount_per_period: i128,
+        amount_decimals: u32,
+        period: RatePeriod,
+        warmup_duration: u64,
+        warmup_curve: Option<WarmupCurve>,
+    ) -> Result<(), Error> {
+        let flow_rate =
+            flow_rate_from_period(&env, &token, amount_per_period, amount_decimals, period)?;
+        Self::create_grant(
+            env,
+            grant_id,
+            recipient,
+            token,
+            total_amount,
+            flow_rate,
+            warmup_duration,
+            warmup_curve,
+            None,
+        )
+    }
+
+    /// The grant's current flow rate expressed as tokens per `period`, fixed-point with
+    /// `amount_decimals` fractional digits.
+    pub fn rate_per_period(
+        env: Env,
+        grant_id: u64,
+        period: RatePeriod,
+        amount_decimals: u32,
+    ) -> Result<i128, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        let preview = preview_grant_at_now(&env, &grant)?;
+        flow_rate_to_period(
+            &env,
+            &preview.token,
+            preview.flow_rate,
+            amount_decimals,
+            period,
+        )
+    }
+
+    pub fn cancel_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        cancel_active_grant(&env, grant_id)
+    }
+
+    /// Anyone may call. Return the part of a cancelled grant that never accrued to the
+    /// treasury. What had accrued by cancellation stays claimable by the grantee.
+    pub fn finalize_cancelled(env: Env, grant_id: u64) -> Result<i128, Error> {
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Cancelled {
+            return Err(Error::InvalidState);
+        }
+
+        let accounted = grant
+            .withdrawn
+            .checked_add(grant.claimable)
+            .ok_or(Error::MathOverflow)?;
+        let remainder = grant
+            .total_amount
+            .checked_sub(accounted)
+            .ok_or(Error::MathOverflow)?;
+        if remainder <= 0 {
+            return Err(Error::NothingToFinalize);
+        }
+
+        lower_total_amount(&mut grant, accounted);
+        write_grant(&env, grant_id, &grant);
+
+        let treasury = read_treasury(&env)?;
+        let client = token::Client::new(&env, &grant.token);
+        client.transfer(&env.current_contract_address(), &treasury, &remainder);
+
+        env.events().publish(
+            (symbol_short!("finalize"), grant_id),
+            (remainder, treasury),
+        );
+
+        Ok(remainder)
+    }
+
+    /// Admin-only. Set up a funding program with its own budget, manager, dates and
+    /// default grant parameters.
+    pub fn create_program(
+        env: Env,
+        program_id: u64,
+        name: String,
+        manager: Address,
+        token: Address,
+        budget: i128,
+        start_time: u64,
+        end_time: u64,
+        defaults: ProgramDefaults,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        if budget <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if end_time <= start_time {
+            return Err(Error::InvalidState);
+        }
+        if i128::from(defaults.warmup_curve.start_bps) > FULL_RATE_BPS {
+            return Err(Error::InvalidWarmup);
+        }
+
+        let key = DataKey::Program(program_id);
+        if env.storage().instance().has(&key) {
+            return Err(Error::ProgramAlreadyExists);
+        }
+
+        let program = Program {
+            name,
+            manager: manager.clone(),
+            token,
+            budget,
+            start_time,
+            end_time,
+            defaults,
+        };
+        env.storage().instance().set(&key, &program);
+
+        env.events().publish(
+            (symbol_short!("program"), program_id),
+            (manager, budget, start_time, end_time),
+        );
+
+        Ok(())
+    }
+
+    /// Admin-only. Change a program's budget. It can't drop below what is already committed.
+    pub fn set_program_budget(env: Env, program_id: u64, budget: i128) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        let mut program = read_program(&env, program_id)?;
+        if budget < committed_budget(&env, program_id)? {
+            return Err(Error::BudgetExceeded);
+        }
+
+        program.budget = budget;
+        env.storage()
+            .instance()
+            .set(&DataKey::Program(program_id), &program);
+        Ok(())
+    }
+
+    /// Manager-only. Create a grant in a program, paid in the program's token and using its
+    /// default parameters. Fails if it would take the program over budget or the program is
+    /// not open.
+    pub fn create_program_grant(
+        env: Env,
+        program_id: u64,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        flow_rate: i128,
+    ) -> Result<(), Error> {
+        let program = read_program(&env, program_id)?;
+        program.manager.require_auth();
+
+        let now = env.ledger().timestamp();
+        if now < program.start_time || now >= program.end_time {
+            return Err(Error::ProgramNotOpen);
+        }
+
+        let used = committed_budget(&env, program_id)?;
+        let committed = used.checked_add(total_amount).ok_or(Error::MathOverflow)?;
+        if committed > program.budget {
+            return Err(Error::BudgetExceeded);
+        }
+
+        insert_grant(
+            &env,
+            GrantParams {
+                grant_id,
+                recipient,
+                token: program.token,
+                total_amount,
+                flow_rate,
+                warmup_duration: program.defaults.warmup_duration,
+                warmup_curve: program.defaults.warmup_curve,
+                rate_schedule: None,
+            },
+        )?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.program_id = Some(program_id);
+        grant.inactivity_threshold = program.defaults.inactivity_threshold;
+        grant.revocable = program.defaults.revocable;
+        write_grant(&env, grant_id, &grant);
+
+        Ok(())
+    }
+
+    pub fn get_program(env: Env, program_id: u64) -> Result<Program, Error> {
+        read_program(&env, program_id)
+    }
+
+    /// Sum of total_amount over a program's grants, to compare against its budget.
+    pub fn program_budget_used(env: Env, program_id: u64) -> Result<i128, Error> {
+        read_program(&env, program_id)?;
+        committed_budget(&env, program_id)
+    }
+
+    /// Like total_allocated_funds, for one program: (total_amount - withdrawn) over its grants.
+    pub fn program_allocated_funds(env: Env, program_id: u64) -> Result<i128, Error> {
+        read_program(&env, program_id)?;
+        sum_program_grants(&env, program_id, |grant| {
+            grant
+                .total_amount
+                .checked_sub(grant.withdrawn)
+                .ok_or(Error::MathOverflow)
+        })
+    }
+
+    /// Admin-only. Open a quadratic funding round, taking `matching_pool` of `token` from
+    /// `funder`. Contributions are accepted from `start_time` until `end_time`.
+    pub fn create_qf_round(
+        env: Env,
+        round_id: u64,
+        token: Address,
+        matching_pool: i128,
+        funder: Address,
+        start_time: u64,
+        end_time: u64,
+        stream_duration: u64,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        funder.require_auth();
+
+        if matching_pool <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if end_time <= start_time || stream_duration == 0 {
+            return Err(Error::InvalidState);
+        }
+
+        let key = DataKey::QfRound(round_id);
+        if env.storage().instance().has(&key) {
+            return Err(Error::QfRoundAlreadyExists);
+        }
+
+        let contract = env.current_contract_address();
+        let received = transfer_measured(&env, &token, &funder, &contract, matching_pool)?;
+
+        let round = QfRound {
+            token,
+            matching_pool: received,
+            start_time,
+            end_time,
+            stream_duration,
+            projects: Vec::new(&env),
+            closed: false,
+        };
+        env.storage().instance().set(&key, &round);
+        let mut open = read_open_qf_rounds(&env);
+        open.push_back(round_id);
+        env.storage().instance().set(&DataKey::OpenQfRounds, &open);
+
+        env.events().publish(
+            (symbol_short!("qfround"), round_id),
+            (received, start_time, end_time),
+        );
+
+        Ok(())
+    }
+
+    /// Admin-only. Make `project` eligible for contributions and matching in a round.
+    pub fn add_qf_project(env: Env, round_id: u64, project: Address) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        let mut round = read_qf_round(&env, round_id)?;
+        if round.closed {
+            return Err(Error::QfRoundClosed);
+        }
+        if round.projects.contains(&project) {
+            return Err(Error::InvalidState);
+        }
+
+        round.projects.push_back(project);
+        env.storage()
+            .instance()
+            .set(&DataKey::QfRound(round_id), &round);
+        Ok(())
+    }
+
+    /// Contributor-only. Give `amount` to a project of an open round. The contribution is
+    /// paid to the project straight away and recorded for matching. Returns what arrived.
+    pub fn contribute(
+        env: Env,
+        round_id: u64,
+        project: Address,
+        contributor: Address,
+        amount: i128,
+    ) -> Result<i128, Error> {
+        contributor.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let round = read_qf_round(&env, round_id)?;
+        let now = env.ledger().timestamp();
+        if round.closed {
+            return Err(Error::QfRoundClosed);
+        }
+        if now < round.start_time || now >= round.end_time {
+            return Err(Error::QfRoundNotOpen);
+        }
+        if !round.projects.contains(&project) {
+            return Err(Error::UnknownProject);
+        }
+
+        let received = transfer_measured(&env, &round.token, &contributor, &project, amount)?;
+
+        let mut contributions = read_qf_contributions(&env, round_id, project.clone());
+        let total = contributions
+            .get(contributor.clone())
+            .unwrap_or(0)
+            .checked_add(received)
+            .ok_or(Error::MathOverflow)?;
+        contributions.set(contributor.clone(), total);
+        write_qf_contributions(&env, round_id, project.clone(), &contributions);
+
+        env.events().publish(
+            (symbol_short!("qfcontrib"), round_id),
+            (project, contributor, received),
+        );
+
+        Ok(received)
+    }
+
+    /// Anyone may call once the round has ended. Split the matching pool between projects
+    /// by quadratic funding, scaled down pro rata if the matches add up to more than the
+    /// pool, and create a grant streaming each project's match over the round's
+    /// stream_duration. All amounts round down; what is left of the pool goes to the treasury.
+    pub fn close_qf_round(env: Env, round_id: u64) -> Result<Vec<QfMatch>, Error> {
+        let mut round = read_qf_round(&env, round_id)?;
+        if round.closed {
+            return Err(Error::QfRoundClosed);
+        }
+
+        let now = env.ledger().timestamp();
+        if now < round.end_time {
+            return Err(Error::QfRoundNotOpen);
+        }
+
+        let mut totals = Vec::new(&env);
+        let mut raw_matches = Vec::new(&env);
+        let mut raw_total = 0_i128;
+        for project in round.projects.iter() {
+            let contributions = read_qf_contributions(&env, round_id, project);
+            let (total, raw_match) = quadratic_match(&contributions)?;
+            totals.push_back(total);
+            raw_matches.push_back(raw_match);
+            raw_total = raw_total.checked_add(raw_match).ok_or(Error::MathOverflow)?;
+        }
+
+        let mut results = Vec::new(&env);
+        let mut paid = 0_i128;
+        for (i, project) in round.projects.iter().enumerate() {
+            let raw_match = raw_matches.get(i as u32).unwrap();
+            let match_amount = if raw_total <= round.matching_pool {
+                raw_match
+            } else {
+                raw_match
+                    .checked_mul(round.matching_pool)
+                    .ok_or(Error::MathOverflow)?
+                    / raw_total
+            };
+
+            let grant_id = if match_amount > 0 {
+                // Round the rate up so the stream finishes within stream_duration.
+                let duration = i128::from(round.stream_duration);
+                let flow_rate = match_amount
+                    .checked_mul(SCALING_FACTOR)
+                    .ok_or(Error::MathOverflow)?
+                    .checked_add(duration - 1)
+                    .ok_or(Error::MathOverflow)?
+                    / duration;
+
+                let grant_id = next_grant_id(&env)?;
+                insert_grant(
+                    &env,
+                    GrantParams {
+                        grant_id,
+                        recipient: project.clone(),
+                        token: round.token.clone(),
+                        total_amount: match_amount,
+                        flow_rate,
+                        warmup_duration: 0,
+                        warmup_curve: DEFAULT_WARMUP_CURVE,
+                        rate_schedule: None,
+                    },
+                )?;
+
+                let mut grant = read_grant(&env, grant_id)?;
+                grant.funded = match_amount;
+                write_grant(&env, grant_id, &grant);
+
+                paid = paid.checked_add(match_amount).ok_or(Error::MathOverflow)?;
+                Some(grant_id)
+            } else {
+                None
+            };
+
+            results.push_back(QfMatch {
+                project,
+                contributions: totals.get(i as u32).unwrap(),
+                match_amount,
+                grant_id,
+            });
+        }
+
+        round.closed = true;
+        env.storage()
+            .instance()
+            .set(&DataKey::QfRound(round_id), &round);
+        let mut open = read_open_qf_rounds(&env);
+        if let Some(index) = open.first_index_of(round_id) {
+            open.remove(index);
+        }
+        env.storage().instance().set(&DataKey::OpenQfRounds, &open);
+
+        let leftover = round
+            .matching_pool
+            .checked_sub(paid)
+            .ok_or(Error::MathOverflow)?;
+        if leftover > 0 {
+            let treasury = read_treasury(&env)?;
+            let client = token::Client::new(&env, &round.token);
+            client.transfer(&env.current_contract_address(), &treasury, &leftover);
+        }
+
+        env.events().publish(
+            (symbol_short!("qfclosed"), round_id),
+            (paid, leftover),
+        );
+
+        Ok(results)
+    }
+
+    pub fn get_qf_round(env: Env, round_id: u64) -> Result<QfRound, Error> {
+        read_qf_round(&env, round_id)
+    }
+
+    /// Total contributed to `project` in a round so far.
+    pub fn qf_contributions(env: Env, round_id: u64, project: Address) -> Result<i128, Error> {
+        read_qf_round(&env, round_id)?;
+        let contributions = read_qf_contributions(&env, round_id, project);
+        let mut total = 0_i128;
+        for (_, amount) in contributions.iter() {
+            total = total.checked_add(amount).ok_or(Error::MathOverflow)?;
+        }
+        Ok(total)
+    }
+
+    /// Admin-only. Create every grant in `grants`, or none if any is invalid. With a
+    /// `funder`, also fund them all with one transfer per token. If a transfer fee is
+    /// charged, each grant is credited its pro rata share of what the contract actually
+    /// received, rounded down, and its total_amount lowered to match.
+    pub fn batch_create_grants(
+        env: Env,
+        grants: Vec<GrantParams>,
+        funder: Option<Address>,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        let mut totals: Map<Address, i128> = Map::new(&env);
+        for params in grants.iter() {
+            let token = params.token.clone();
+            let amount = params.total_amount;
+            insert_grant(&env, params)?;
+
+            let total = totals
+                .get(token.clone())
+                .unwrap_or(0)
+                .checked_add(amount)
+                .ok_or(Error::MathOverflow)?;
+            totals.set(token, total);
+        }
+
+        if let Some(funder) = funder.clone() {
+            funder.require_auth();
+
+            let contract = env.current_contract_address();
+            for (token, total) in totals.iter() {
+                let received = transfer_measured(&env, &token, &funder, &contract, total)?;
+                for params in grants.iter() {
+                    if params.token != token {
+                        continue;
+                    }
+                    let credit = params
+                        .total_amount
+                        .checked_mul(received)
+                        .ok_or(Error::MathOverflow)?
+                        / total;
+                    let mut grant = read_grant(&env, params.grant_id)?;
+                    credit_funding(&mut grant, params.total_amount, credit)?;
+                    write_grant(&env, params.grant_id, &grant);
+                }
+            }
+        }
+
+        env.events().publish(
+            (symbol_short!("batchnew"),),
+            (grants.len(), funder),
+        );
+
+        Ok(())
+    }
+
+    /// Admin-only. Cancel every grant in `grant_ids`, or none if any can't be cancelled.
+    pub fn batch_cancel(env: Env, grant_ids: Vec<u64>) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        for grant_id in grant_ids.iter() {
+            cancel_active_grant(&env, grant_id)?;
+        }
+        Ok(())
+    }
+
+    /// Admin-only. Apply propose_rate_change to each (grant_id, new_rate) pair, or to none
+    /// if any is rejected.
+    pub fn batch_update_rate(env: Env, updates: Vec<(u64, i128)>) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        for (grant_id, new_rate) in updates.iter() {
+            change_rate(&env, grant_id, new_rate, None)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_grant(env: Env, grant_id: u64) -> Result<Grant, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        preview_grant_at_now(&env, &grant)
+    }
+
+    /// Scheduled rate segments that have not started yet.
+    pub fn upcoming_rate_segments(env: Env, grant_id: u64) -> Result<Vec<RateSegment>, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        let preview = preview_grant_at_now(&env, &grant)?;
+        Ok(preview.rate_schedule)
+    }
+
+    pub fn claimable(env: Env, grant_id: u64) -> Result<i128, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        let preview = preview_grant_at_now(&env, &grant)?;
+        Ok(preview.claimable)
+    }
+
+    pub fn withdraw(env: Env, grant_id: u64, amount: i128) -> Result<(), Error> {
+        let recipient = read_grant(&env, grant_id)?.recipient;
+        Self::withdraw_to(env, grant_id, amount, recipient)
+    }
+
+    /// Recipient-only. Withdraw `amount` of a grant's claimable balance and send it to `to`
+    /// instead of the recipient, e.g. an exchange, a multisig or a payroll contract.
+    pub fn withdraw_to(env: Env, grant_id: u64, amount: i128, to: Address) -> Result<(), Error> {
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        let recipient = grant.recipient.clone();
+        withdraw_grant(&env, grant_id, grant, amount, &recipient, &to)
+    }
+
+    /// Delegate-only. Withdraw `amount` on the recipient's behalf. The funds always go to
+    /// the recipient; the delegate only needs its own authorization, not the recipient's.
+    pub fn withdraw_as_delegate(
+        env: Env,
+        grant_id: u64,
+        delegate: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        delegate.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let grant = read_grant(&env, grant_id)?;
+        if grant.claim_delegate != Some(delegate.clone()) {
+            return Err(Error::NotAuthorized);
+        }
+
+        let recipient = grant.recipient.clone();
+        withdraw_grant(&env, grant_id, grant, amount, &delegate, &recipient)
+    }
+
+    /// Recipient-only. Choose whether payouts call `on_grant_payment(grant_id, amount)`
+    /// on the contract receiving the funds, and whether a failing hook reverts them.
+    /// A Required hook can't be combined with automatic payouts: process_payouts pays
+    /// many grants at once and can't revert a single one of them.
+    pub fn set_payment_hook(env: Env, grant_id: u64, hook: PaymentHook) -> Result<(), Error> {
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        if hook == PaymentHook::Required && grant.payout_interval != 0 {
+            return Err(Error::InvalidState);
+        }
+
+        grant.payment_hook = hook;
+        write_grant(&env, grant_id, &grant);
+        Ok(())
+    }
+
+    /// Current owner of a grant's position: the address entitled to withdraw from it.
+    pub fn owner_of(env: Env, grant_id: u64) -> Result<Address, Error> {
+        Ok(read_grant(&env, grant_id)?.recipient)
+    }
+
+    /// Owner-only. Transfer a grant's position to `to`, who takes over the right to
+    /// withdraw everything not yet withdrawn, including the current claimable balance.
+    pub fn transfer_position(
+        env: Env,
+        grant_id: u64,
+        from: Address,
+        to: Address,
+    ) -> Result<(), Error> {
+        from.require_auth();
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.recipient != from {
+            return Err(Error::NotAuthorized);
+        }
+        if !grant.transferable {
+            return Err(Error::NotTransferable);
+        }
+        ensure_not_disputed(&grant)?;
+        if !grant.shares.is_empty() {
+            return Err(Error::SplitGrant);
+        }
+
+        grant.recipient = to.clone();
+        // Delegation, payout and hook settings were chosen by the previous owner.
+        grant.claim_delegate = None;
+        grant.payout_interval = 0;
+        grant.payment_hook = PaymentHook::Off;
+        write_grant(&env, grant_id, &grant);
+        remove_recipient_grant(&env, &from, grant_id);
+        add_recipient_grant(&env, &to, grant_id);
+
+        env.events().publish((symbol_short!("transfer"), grant_id), (from, to));
+
+        Ok(())
+    }
+
+    /// Admin-only. Allow or forbid transfer_position for a grant.
+    pub fn set_transferable(env: Env, grant_id: u64, transferable: bool) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.transferable = transferable;
+        write_grant(&env, grant_id, &grant);
+        Ok(())
+    }
+
+    /// Recipient-only. Opt in to automatic payouts every `interval_secs`, pushed to the
+    /// recipient by process_payouts. 0 turns them off. Not available with a Required
+    /// payment hook.
+    pub fn set_payout_interval(env: Env, grant_id: u64, interval_secs: u64) -> Result<(), Error> {
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        if interval_secs != 0 && grant.payment_hook == PaymentHook::Required {
+            return Err(Error::InvalidState);
+        }
+
+        grant.payout_interval = interval_secs;
+        grant.last_payout_at = env.ledger().timestamp();
+        write_grant(&env, grant_id, &grant);
+        Ok(())
+    }
+
+    /// Anyone may call. Pay out the claimable balance of grants whose payout interval has
+    /// elapsed, looking at up to `limit` grants from position `start` of the grant list.
+    /// `keeper` earns the payout fee configured for each grant's token out of the payout.
+    /// A grant that can't be paid, e.g. because the transfer to its recipient fails, is
+    /// skipped rather than failing the batch, and a keeper fee that can't be paid stays with
+    /// the grant. Under FeePolicy::Contract, a transfer fee the contract can't cover from its
+    /// reserve is borne by the recipient.
+    /// Returns the amount paid out of each grant, fee included.
+    pub fn process_payouts(
+        env: Env,
+        keeper: Address,
+        start: u32,
+        limit: u32,
+    ) -> Result<Vec<GrantWithdrawal>, Error> {
+        keeper.require_auth();
 
         let now = env.ledger().timestamp();
-        let grant = Grant {
-            recipient,
-            total_amount,
-            withdrawn: 0,
-            claimable: 0,
-            flow_rate,
-            last_update_ts: now,
-            rate_updated_at: now,
-            last_claim_time: now,
-            pending_rate: 0,
-            effective_timestamp: 0,
-            status: GrantStatus::Active,
-            start_time: now,
-            warmup_duration,
+        let ids = read_grant_ids(&env);
+        let end = start.saturating_add(limit).min(ids.len());
+        let mut paid = Vec::new(&env);
+
+        let contract = env.current_contract_address();
+        for i in start..end {
+            let grant_id = ids.get(i).unwrap();
+            let Ok(mut grant) = read_grant(&env, grant_id) else {
+                continue;
+            };
+
+            if grant.payout_interval == 0
+                || grant.dispute.opened_by.is_some()
+                || !grant.shares.is_empty()
+                || now < grant.last_payout_at.saturating_add(grant.payout_interval)
+            {
+                continue;
+            }
+
+            if settle_grant_and_notify(&env, grant_id, &mut grant, now).is_err() {
+                continue;
+            }
+            let mut amount = withdrawable(&grant);
+            if amount <= 0 {
+                continue;
+            }
+
+            let settled = grant.clone();
+            if debit_claimable(&mut grant, amount, now).is_err() {
+                continue;
+            }
+            // A keeper pushing funds is not grantee activity for inactivity slashing.
+            grant.last_claim_time = settled.last_claim_time;
+            grant.inactive_flagged_at = settled.inactive_flagged_at;
+            grant.last_payout_at = now;
+
+            let fee_key = DataKey::PayoutFee(grant.token.clone());
+            let Ok(mut fee) = keeper_reward_for(&env, &fee_key, amount) else {
+                continue;
+            };
+            let recipient = grant.recipient.clone();
+            let net = amount - fee;
+            write_grant(&env, grant_id, &grant);
+
+            let mut received = 0_i128;
+            if net > 0 {
+                let Some(arrived) = try_pay_out(&env, grant_id, &mut grant, &recipient, net)
+                else {
+                    write_grant(&env, grant_id, &settled);
+                    continue;
+                };
+                received = arrived;
+            }
+
+            if received > 0 {
+                // Payouts never run with a Required hook, so this can't fail the batch.
+                let hook = grant.payment_hook;
+                let _ = call_payment_hook(&env, hook, &recipient, grant_id, received);
+            }
+
+            if fee > 0 {
+                let client = token::Client::new(&env, &grant.token);
+                if !matches!(client.try_transfer(&contract, &keeper, &fee), Ok(Ok(()))) {
+                    // The keeper goes unpaid; the fee stays with the grant.
+                    if refund_fee_shortfall(&mut grant, fee).is_ok() {
+                        write_grant(&env, grant_id, &grant);
+                        amount -= fee;
+                        fee = 0;
+                    }
+                }
+            }
+
+            env.events().publish(
+                (symbol_short!("payout"), grant_id),
+                (recipient, amount, keeper.clone(), fee),
+            );
+
+            paid.push_back(GrantWithdrawal {
+                grant_id,
+                token: grant.token.clone(),
+                amount,
+            });
+        }
+
+        Ok(paid)
+    }
+
+    /// Admin-only. Pay process_payouts callers `bps` of each payout, at most `cap` base
+    /// units, for grants paid in `token`. `bps = 0` disables the fee.
+    pub fn set_payout_fee(env: Env, token: Address, bps: u32, cap: i128) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        if i128::from(bps) > FULL_RATE_BPS || cap < 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::PayoutFee(token), &KeeperBounty { bps, cap });
+        Ok(())
+    }
+
+    /// Recipient-only. Let `delegate` (e.g. a hot key or relayer) trigger withdrawals to the
+    /// recipient. Replaces any previous delegate.
+    pub fn set_claim_delegate(env: Env, grant_id: u64, delegate: Address) -> Result<(), Error> {
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        grant.claim_delegate = Some(delegate.clone());
+        write_grant(&env, grant_id, &grant);
+
+        env.events().publish(
+            (symbol_short!("delegate"), grant_id),
+            (grant.recipient, Some(delegate)),
+        );
+
+        Ok(())
+    }
+
+    /// Recipient-only. Remove the grant's claim delegate.
+    pub fn revoke_claim_delegate(env: Env, grant_id: u64) -> Result<(), Error> {
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        grant.claim_delegate = None;
+        write_grant(&env, grant_id, &grant);
+
+        env.events().publish(
+            (symbol_short!("delegate"), grant_id),
+            (grant.recipient, None::<Address>),
+        );
+
+        Ok(())
+    }
+
+    /// Withdraw the full claimable balance of every grant owned by `recipient`
+    /// with a single transfer per token. Returns the amount taken from each grant.
+    pub fn withdraw_all(env: Env, recipient: Address) -> Result<Vec<GrantWithdrawal>, Error> {
+        recipient.require_auth();
+
+        let now = env.ledger().timestamp();
+        let ids = read_recipient_grants(&env, &recipient);
+        let mut breakdown = Vec::new(&env);
+        let mut totals: Map<Address, i128> = Map::new(&env);
+
+        for i in 0..ids.len() {
+            let grant_id = ids.get(i).unwrap();
+            let mut grant = read_grant(&env, grant_id)?;
+
+            if grant.dispute.opened_by.is_some() || !grant.shares.is_empty() {
+                continue;
+            }
+
+            settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+
+            let amount = withdrawable(&grant);
+            if amount > 0 {
+                debit_claimable(&mut grant, amount, now)?;
+                let total = totals
+                    .get(grant.token.clone())
+                    .unwrap_or(0)
+                    .checked_add(amount)
+                    .ok_or(Error::MathOverflow)?;
+                totals.set(grant.token.clone(), total);
+                breakdown.push_back(GrantWithdrawal {
+                    grant_id,
+                    token: grant.token.clone(),
+                    amount,
+                });
+            }
+
+            write_grant(&env, grant_id, &grant);
+        }
+
+        if breakdown.is_empty() {
+            return Err(Error::NothingToWithdraw);
+        }
+
+        // One transfer per distinct token across the recipient's grants.
+        let contract = env.current_contract_address();
+        let policy = read_fee_policy(&env);
+        let mut received = Vec::new(&env);
+        for entry in breakdown.iter() {
+            received.push_back(entry.amount);
+        }
+        for (token, total) in totals.iter() {
+            let arrived = transfer_measured(&env, &token, &contract, &recipient, total)?;
+            if arrived < total {
+                let shortfall = total.checked_sub(arrived).ok_or(Error::MathOverflow)?;
+                spread_fee_shortfall(&breakdown, &mut received, &token, shortfall)?;
+                if policy == FeePolicy::Contract && reserve_covers(&env, &token, shortfall)? {
+                    refund_fee_shortfall_across(&env, &mut breakdown, &received, &token)?;
+                }
+            }
+        }
+
+        for (entry, amount) in breakdown.iter().zip(received.iter()) {
+            let hook = read_grant(&env, entry.grant_id)?.payment_hook;
+            call_payment_hook(&env, hook, &recipient, entry.grant_id, amount)?;
+        }
+
+        env.events().publish(
+            (symbol_short!("wdrawall"), recipient),
+            breakdown.len(),
+        );
+
+        Ok(breakdown)
+    }
+
+    /// Admin-only. Split a grant's stream between several recipients by share. Accrual up to
+    /// now is credited under the old split first, so a change only affects future accrual.
+    pub fn set_recipient_shares(
+        env: Env,
+        grant_id: u64,
+        shares: Vec<RecipientShare>,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        validate_shares(&shares)?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+        ensure_not_disputed(&grant)?;
+
+        settle_grant_and_notify(&env, grant_id, &mut grant, env.ledger().timestamp())?;
+
+        if grant.shares.is_empty() {
+            // Whatever the single recipient had accrued and not withdrawn stays theirs.
+            let balance = grant
+                .share_balances
+                .get(grant.recipient.clone())
+                .unwrap_or(0)
+                .checked_add(grant.claimable)
+                .ok_or(Error::MathOverflow)?;
+            grant.share_balances.set(grant.recipient.clone(), balance);
+            grant.shares_accrued_base = grant
+                .withdrawn
+                .checked_add(grant.claimable)
+                .ok_or(Error::MathOverflow)?;
+        } else {
+            update_share_balances(&env, &mut grant)?;
+        }
+        grant.shares = shares.clone();
+        write_grant(&env, grant_id, &grant);
+
+        env.events().publish((symbol_short!("shares"), grant_id), shares);
+
+        Ok(())
+    }
+
+    /// Co-recipient-only. Withdraw from `recipient`'s own balance of a split grant.
+    pub fn withdraw_share(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        recipient.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut grant = read_grant(&env, grant_id)?;
+        ensure_not_disputed(&grant)?;
+        if grant.shares.is_empty() {
+            return Err(Error::InvalidState);
+        }
+
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+        update_share_balances(&env, &mut grant)?;
+
+        let balance = grant
+            .share_balances
+            .get(recipient.clone())
+            .ok_or(Error::NotAuthorized)?;
+        if amount > balance {
+            return Err(Error::InvalidAmount);
+        }
+
+        debit_claimable(&mut grant, amount, now)?;
+        grant.share_balances.set(recipient.clone(), balance - amount);
+        write_grant(&env, grant_id, &grant);
+
+        let debited = grant.withdrawn;
+        let received = pay_out(&env, grant_id, &mut grant, &recipient, amount)?;
+        if grant.withdrawn < debited {
+            // pay_out returned the fee to the grant's claimable; it belongs to this recipient.
+            grant.share_balances.set(recipient.clone(), balance - received);
+            write_grant(&env, grant_id, &grant);
+        }
+
+        env.events().publish(
+            (symbol_short!("withdraw"), grant_id),
+            (recipient.clone(), recipient.clone(), amount, received),
+        );
+
+        call_payment_hook(&env, grant.payment_hook, &recipient, grant_id, received)
+    }
+
+    /// What `recipient` can currently withdraw from a split grant.
+    pub fn share_claimable(env: Env, grant_id: u64, recipient: Address) -> Result<i128, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        let mut preview = preview_grant_at_now(&env, &grant)?;
+        update_share_balances(&env, &mut preview)?;
+        Ok(preview.share_balances.get(recipient).unwrap_or(0))
+    }
+
+    /// Deposit `amount` of a grant's token into the contract. Credits the grant with
+    /// the amount actually received, which is less than `amount` for fee-on-transfer tokens;
+    /// the fee is taken off the grant's total_amount.
+    pub fn fund_grant(env: Env, grant_id: u64, from: Address, amount: i128) -> Result<i128, Error> {
+        from.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+
+        let contract = env.current_contract_address();
+        let received = transfer_measured(&env, &grant.token, &from, &contract, amount)?;
+        credit_funding(&mut grant, amount, received)?;
+        write_grant(&env, grant_id, &grant);
+
+        env.events().publish(
+            (symbol_short!("funded"), grant_id),
+            (from, amount, received),
+        );
+
+        Ok(received)
+    }
+
+    /// Admin-only. Reconcile the contract's balance of `token` with what its grants still
+    /// owe, for rebasing tokens whose balances change without transfers. A surplus from a
+    /// positive rebase goes to the treasury; a shortfall from a negative one is only
+    /// reported, as the contract has nothing to cover it with. Returns balance minus owed.
+    /// The surplus includes any reserve kept for FeePolicy::Contract; once it is swept,
+    /// recipients bear transfer fees until the reserve is topped up.
+    pub fn sync_rebase(env: Env, token: Address) -> Result<i128, Error> {
+        require_admin_auth(&env)?;
+
+        let contract = env.current_contract_address();
+        let client = token::Client::new(&env, &token);
+        let delta = client
+            .balance(&contract)
+            .checked_sub(total_allocated_funds(&env, &token)?)
+            .ok_or(Error::MathOverflow)?;
+
+        if delta > 0 {
+            let treasury = read_treasury(&env)?;
+            client.transfer(&contract, &treasury, &delta);
+        }
+
+        env.events().publish((symbol_short!("rebase"), token), delta);
+
+        Ok(delta)
+    }
+
+    /// Admin-only. Choose who absorbs fees charged by fee-on-transfer tokens on withdrawal.
+    pub fn set_fee_policy(env: Env, policy: FeePolicy) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        env.storage().instance().set(&DataKey::FeePolicy, &policy);
+        Ok(())
+    }
+
+    pub fn fee_policy(env: Env) -> FeePolicy {
+        read_fee_policy(&env)
+    }
+
+    /// IDs of every grant currently assigned to `recipient`.
+    pub fn get_recipient_grants(env: Env, recipient: Address) -> Vec<u64> {
+        read_recipient_grants(&env, &recipient)
+    }
+
+    /// Anyone may call. First phase of an inactivity slash: once the grantee has been inactive
+    /// past the grant's threshold, flag the grant and start the grace window.
+    pub fn flag_inactive(env: Env, grant_id: u64) -> Result<u64, Error> {
+        let mut grant = read_grant(&env, grant_id)?;
+
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+        ensure_not_disputed(&grant)?;
+
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+
+        if grant.status != GrantStatus::Active {
+            write_grant(&env, grant_id, &grant);
+            return Err(Error::InvalidState);
+        }
+
+        let inactive_secs = now.saturating_sub(grant.last_claim_time);
+        if inactive_secs < inactivity_threshold_for(&env, &grant) {
+            return Err(Error::GrantNotInactive);
+        }
+
+        if grant.inactive_flagged_at != 0 {
+            return Err(Error::AlreadyFlaggedInactive);
+        }
+
+        grant.inactive_flagged_at = now;
+        write_grant(&env, grant_id, &grant);
+
+        let grace_ends_at = now
+            .checked_add(read_slash_grace_period(&env))
+            .ok_or(Error::MathOverflow)?;
+        env.events().publish(
+            (symbol_short!("flagged"), grant_id),
+            (grant.recipient.clone(), grace_ends_at),
+        );
+
+        Ok(grace_ends_at)
+    }
+
+    /// Anyone may call. Slash a grant flagged by `flag_inactive` once its grace window has passed
+    /// without grantee activity. A full slash cancels the grant and returns everything not yet
+    /// withdrawn to the treasury; a partial slash takes `slash_bps` of it and the grant continues.
+    /// `caller` receives the keeper bounty configured for the grant's token out of the slashed
+    /// funds; the bounty paid is returned.
+    pub fn slash_inactive_grant(env: Env, grant_id: u64, caller: Address) -> Result<i128, Error> {
+        caller.require_auth();
+
+        let mut grant = read_grant(&env, grant_id)?;
+
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+        ensure_not_disputed(&grant)?;
+
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+
+        if grant.status != GrantStatus::Active {
+            write_grant(&env, grant_id, &grant);
+            return Err(Error::InvalidState);
+        }
+
+        let inactive_secs = now.saturating_sub(grant.last_claim_time);
+        if inactive_secs < inactivity_threshold_for(&env, &grant) {
+            return Err(Error::GrantNotInactive);
+        }
+
+        if grant.inactive_flagged_at == 0 {
+            return Err(Error::NotFlaggedInactive);
+        }
+
+        let grace_ends_at = grant
+            .inactive_flagged_at
+            .checked_add(read_slash_grace_period(&env))
+            .ok_or(Error::MathOverflow)?;
+        if now < grace_ends_at {
+            return Err(Error::GracePeriodActive);
+        }
+
+        let remaining = grant
+            .total_amount
+            .checked_sub(grant.withdrawn)
+            .ok_or(Error::MathOverflow)?;
+        update_share_balances(&env, &mut grant)?;
+
+        let slashed = if i128::from(grant.slash_bps) >= FULL_RATE_BPS {
+            close_grant(&env, &mut grant);
+            // Accrued but unwithdrawn funds go to the treasury too, so nothing is left to claim.
+            let claimable = grant.claimable;
+            grant.claimable = 0;
+            let withdrawn = grant.withdrawn;
+            lower_total_amount(&mut grant, withdrawn);
+            debit_share_balances(&mut grant, claimable)?;
+            remaining
+        } else {
+            let slashed = remaining
+                .checked_mul(i128::from(grant.slash_bps))
+                .ok_or(Error::MathOverflow)?
+                / FULL_RATE_BPS;
+            let total_amount = grant
+                .total_amount
+                .checked_sub(slashed)
+                .ok_or(Error::MathOverflow)?;
+            lower_total_amount(&mut grant, total_amount);
+            let unwithdrawn = grant
+                .total_amount
+                .checked_sub(grant.withdrawn)
+                .ok_or(Error::MathOverflow)?;
+            if grant.claimable > unwithdrawn {
+                let cut = grant.claimable - unwithdrawn;
+                grant.claimable = unwithdrawn;
+                debit_share_balances(&mut grant, cut)?;
+            }
+            // Restart the inactivity clock so the next partial slash needs another full period.
+            grant.last_claim_time = now;
+            slashed
         };
+        grant.inactive_flagged_at = 0;
+        write_grant(&env, grant_id, &grant);
+        if grant.status == GrantStatus::Cancelled {
+            cancel_subgrants(&env, &grant, now)?;
+        }
+
+        let bounty_key = DataKey::KeeperBounty(grant.token.clone());
+        let bounty = keeper_reward_for(&env, &bounty_key, slashed)?;
+        let to_treasury = slashed.checked_sub(bounty).ok_or(Error::MathOverflow)?;
+
+        let contract = env.current_contract_address();
+        let client = token::Client::new(&env, &grant.token);
+        if bounty > 0 {
+            client.transfer(&contract, &caller, &bounty);
+        }
+        if to_treasury > 0 {
+            let treasury = read_treasury(&env)?;
+            client.transfer(&contract, &treasury, &to_treasury);
+        }
 
-        env.storage().instance().set(&key, &grant);
-        let mut ids = read_grant_ids(&env);
-        ids.push_back(grant_id);
-        env.storage().instance().set(&DataKey::GrantIds, &ids);
-        Ok(())
+        env.events().publish(
+            (symbol_short!("slashed"), grant_id),
+            (slashed, grant.status.clone(), caller, bounty),
+        );
+
+        Ok(bounty)
     }
 
-    pub fn cancel_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+    /// Admin-only. Reward callers of slash_inactive_grant with `bps` of the slashed amount,
+    /// at most `cap` base units, for grants paid in `token`. `bps = 0` disables the bounty.
+    pub fn set_keeper_bounty(env: Env, token: Address, bps: u32, cap: i128) -> Result<(), Error> {
         require_admin_auth(&env)?;
-        let mut grant = read_grant(&env, grant_id)?;
 
+        if i128::from(bps) > FULL_RATE_BPS || cap < 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::KeeperBounty(token), &KeeperBounty { bps, cap });
+        Ok(())
+    }
+
+    /// Grantee-only. Record activity without withdrawing, resetting the inactivity clock.
+    pub fn heartbeat(env: Env, grant_id: u64) -> Result<(), Error> {
+        let mut grant = read_grant(&env, grant_id)?;
         if grant.status != GrantStatus::Active {
             return Err(Error::InvalidState);
         }
 
-        settle_grant(&mut grant, env.ledger().timestamp())?;
-        grant.flow_rate = 0;
-        grant.pending_rate = 0;
-        grant.effective_timestamp = 0;
-        grant.status = GrantStatus::Cancelled;
+        grant.recipient.require_auth();
+
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+        grant.last_claim_time = now;
+        grant.inactive_flagged_at = 0;
         write_grant(&env, grant_id, &grant);
 
+        env.events().publish((symbol_short!("heartbt"), grant_id), now);
+
         Ok(())
     }
 
-    pub fn get_grant(env: Env, grant_id: u64) -> Result<Grant, Error> {
-        let grant = read_grant(&env, grant_id)?;
-        preview_grant_at_now(&env, &grant)
+    /// Admin-only. Set a grant's inactivity threshold (0 = contract default) and the share of
+    /// its remaining balance an inactivity slash takes.
+    pub fn set_slashing_policy(
+        env: Env,
+        grant_id: u64,
+        inactivity_threshold: u64,
+        slash_bps: u32,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        if slash_bps == 0 || i128::from(slash_bps) > FULL_RATE_BPS {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+
+        grant.inactivity_threshold = inactivity_threshold;
+        grant.slash_bps = slash_bps;
+        write_grant(&env, grant_id, &grant);
+        Ok(())
     }
 
-    pub fn claimable(env: Env, grant_id: u64) -> Result<i128, Error> {
-        let grant = read_grant(&env, grant_id)?;
-        let preview = preview_grant_at_now(&env, &grant)?;
-        Ok(preview.claimable)
+    /// Admin-only. How long a grantee has to act after their grant is flagged inactive.
+    pub fn set_slash_grace_period(env: Env, grace_secs: u64) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::SlashGracePeriod, &grace_secs);
+        Ok(())
     }
 
-    pub fn withdraw(env: Env, grant_id: u64, amount: i128) -> Result<(), Error> {
-        if amount <= 0 {
+    /// Admin-only. Inactivity threshold used by grants that don't set their own.
+    pub fn set_default_inactivity_threshold(env: Env, threshold_secs: u64) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        if threshold_secs == 0 {
             return Err(Error::InvalidAmount);
         }
 
+        env.storage()
+            .instance()
+            .set(&DataKey::DefaultInactivityThreshold, &threshold_secs);
+        Ok(())
+    }
+
+    /// Admin or grantee. Open a dispute on an active grant, freezing withdrawals and rate
+    /// changes until an arbitrator resolves it. The grant keeps accruing in the meantime.
+    /// The admin can still cancel a grant whose dispute the grantee opened.
+    pub fn open_dispute(
+        env: Env,
+        grant_id: u64,
+        caller: Address,
+        reason: Option<BytesN<32>>,
+    ) -> Result<(), Error> {
+        caller.require_auth();
+
         let mut grant = read_grant(&env, grant_id)?;
+        if caller != grant.recipient && caller != read_admin(&env)? {
+            return Err(Error::NotAuthorized);
+        }
 
-        if grant.status == GrantStatus::Cancelled {
+        if grant.status != GrantStatus::Active {
             return Err(Error::InvalidState);
         }
+        ensure_not_disputed(&grant)?;
 
-        grant.recipient.require_auth();
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
 
-        settle_grant(&mut grant, env.ledger().timestamp())?;
+        grant.dispute = Dispute {
+            opened_by: Some(caller.clone()),
+            opened_at: now,
+            reason: reason.clone(),
+        };
+        write_grant(&env, grant_id, &grant);
+        freeze_subgrants(&env, &grant, now)?;
 
-        if amount > grant.claimable {
-            return Err(Error::InvalidAmount);
-        }
+        env.events().publish(
+            (symbol_short!("disputed"), grant_id),
+            (caller, reason),
+        );
 
-        grant.claimable = grant
-            .claimable
-            .checked_sub(amount)
-            .ok_or(Error::MathOverflow)?;
-        grant.withdrawn = grant
-            .withdrawn
-            .checked_add(amount)
-            .ok_or(Error::MathOverflow)?;
+        Ok(())
+    }
 
-        let accounted = grant
-            .withdrawn
-            .checked_add(grant.claimable)
-            .ok_or(Error::MathOverflow)?;
+    /// Arbitrator-only, or admin-only while no arbitrators are set. Close the open dispute on
+    /// a grant by resuming it, cancelling it, or cancelling it and splitting its unwithdrawn
+    /// balance between grantee and treasury. On a split grant the grantee part is paid to the
+    /// co-recipients by share.
+    pub fn resolve_dispute(
+        env: Env,
+        grant_id: u64,
+        arbitrator: Address,
+        resolution: DisputeResolution,
+    ) -> Result<(), Error> {
+        arbitrator.require_auth();
 
-        if accounted > grant.total_amount {
-            return Err(Error::InvalidState);
+        let arbitrators = read_arbitrators(&env);
+        let allowed = if arbitrators.is_empty() {
+            arbitrator == read_admin(&env)?
+        } else {
+            arbitrators.contains(&arbitrator)
+        };
+        if !allowed {
+            return Err(Error::NotAuthorized);
         }
 
-        if grant.withdrawn == grant.total_amount {
-            grant.status = GrantStatus::Completed;
+        if let DisputeResolution::Split(grantee_bps) = resolution {
+            if i128::from(grantee_bps) > FULL_RATE_BPS {
+                return Err(Error::InvalidAmount);
+            }
+        }
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.dispute.opened_by.is_none() {
+            return Err(Error::NotUnderDispute);
         }
 
-        grant.last_claim_time = env.ledger().timestamp();
+        let now = env.ledger().timestamp();
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+        let dispute = grant.dispute.clone();
+
+        let mut payees: Map<Address, i128> = Map::new(&env);
+        let mut to_grantee = 0_i128;
+        let mut to_treasury = 0_i128;
+        match resolution {
+            DisputeResolution::Resume => lift_dispute(&mut grant, now),
+            DisputeResolution::Cancel => {
+                grant.dispute = Dispute::default();
+                close_grant(&env, &mut grant);
+            }
+            DisputeResolution::Split(grantee_bps) => {
+                grant.dispute = Dispute::default();
+                let remaining = grant
+                    .total_amount
+                    .checked_sub(grant.withdrawn)
+                    .ok_or(Error::MathOverflow)?;
+                let grantee_part = |amount: i128| {
+                    amount
+                        .checked_mul(i128::from(grantee_bps))
+                        .ok_or(Error::MathOverflow)
+                        .map(|v| v / FULL_RATE_BPS)
+                };
+
+                if grant.shares.is_empty() {
+                    payees.set(grant.recipient.clone(), grantee_part(remaining)?);
+                } else {
+                    // Each co-recipient gets the grantee part of their own balance, and of
+                    // the unaccrued rest by share.
+                    update_share_balances(&env, &mut grant)?;
+                    for (recipient, balance) in grant.share_balances.iter() {
+                        payees.set(recipient, grantee_part(balance)?);
+                    }
+                    let unaccrued = remaining
+                        .checked_sub(grant.claimable)
+                        .ok_or(Error::MathOverflow)?;
+                    let parts = split_by_shares(&env, &grant.shares, grantee_part(unaccrued)?)?;
+                    for (recipient, part) in parts.iter() {
+                        let owed = payees
+                            .get(recipient.clone())
+                            .unwrap_or(0)
+                            .checked_add(part)
+                            .ok_or(Error::MathOverflow)?;
+                        payees.set(recipient, owed);
+                    }
+                    grant.share_balances = Map::new(&env);
+                }
+
+                for (_, amount) in payees.iter() {
+                    to_grantee = to_grantee.checked_add(amount).ok_or(Error::MathOverflow)?;
+                }
+                to_treasury = remaining
+                    .checked_sub(to_grantee)
+                    .ok_or(Error::MathOverflow)?;
+
+                close_grant(&env, &mut grant);
+                grant.withdrawn = grant
+                    .withdrawn
+                    .checked_add(to_grantee)
+                    .ok_or(Error::MathOverflow)?;
+                let withdrawn = grant.withdrawn;
+                lower_total_amount(&mut grant, withdrawn);
+                grant.claimable = 0;
+                grant.shares_accrued_base = withdrawn;
+            }
+        }
         write_grant(&env, grant_id, &grant);
+        if grant.status == GrantStatus::Cancelled {
+            cancel_subgrants(&env, &grant, now)?;
+        } else {
+            resume_subgrants(&env, &grant, &dispute, now)?;
+        }
+
+        let contract = env.current_contract_address();
+        let client = token::Client::new(&env, &grant.token);
+        for (payee, amount) in payees.iter() {
+            if amount > 0 {
+                client.transfer(&contract, &payee, &amount);
+            }
+        }
+        if to_treasury > 0 {
+            let treasury = read_treasury(&env)?;
+            client.transfer(&contract, &treasury, &to_treasury);
+        }
+
+        env.events().publish(
+            (symbol_short!("dispres"), grant_id),
+            (arbitrator, resolution, to_grantee, to_treasury),
+        );
+
         Ok(())
     }
 
-    /// Anyone may call. Cancel an active grant if the grantee has not claimed in 90+ days; return remaining funds to treasury.
-    pub fn slash_inactive_grant(env: Env, grant_id: u64) -> Result<(), Error> {
-        let mut grant = read_grant(&env, grant_id)?;
+    /// Admin-only. Propose reclaiming `amount` of a grant's accrued, unwithdrawn balance for
+    /// misconduct, citing `reason_code`. The amount is held back from withdrawals at once and
+    /// sent to the treasury by execute_clawback after the timelock. Returns when it can execute.
+    pub fn clawback(env: Env, grant_id: u64, amount: i128, reason_code: u32) -> Result<u64, Error> {
+        require_admin_auth(&env)?;
 
-        if grant.status != GrantStatus::Active {
-            return Err(Error::InvalidState);
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.pending_clawback.amount > 0 {
+            return Err(Error::ClawbackPending);
         }
 
         let now = env.ledger().timestamp();
-        settle_grant(&mut grant, now)?;
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
 
-        if grant.status != GrantStatus::Active {
-            write_grant(&env, grant_id, &grant);
-            return Err(Error::InvalidState);
+        if amount > grant.claimable {
+            return Err(Error::InvalidAmount);
         }
 
-        let inactive_secs = now.saturating_sub(grant.last_claim_time);
-        if inactive_secs < INACTIVITY_THRESHOLD_SECS {
-            return Err(Error::GrantNotInactive);
+        let executable_at = now
+            .checked_add(CLAWBACK_TIMELOCK_SECS)
+            .ok_or(Error::MathOverflow)?;
+        grant.pending_clawback = PendingClawback {
+            amount,
+            reason_code,
+            executable_at,
+        };
+        write_grant(&env, grant_id, &grant);
+
+        env.events().publish(
+            (symbol_short!("clawprop"), grant_id),
+            (amount, reason_code, executable_at),
+        );
+
+        Ok(executable_at)
+    }
+
+    /// Admin-only. Carry out a pending clawback whose timelock has passed, sending the
+    /// amount to the treasury and removing it from the grant.
+    pub fn execute_clawback(env: Env, grant_id: u64) -> Result<i128, Error> {
+        require_admin_auth(&env)?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        let clawback = grant.pending_clawback.clone();
+        if clawback.amount == 0 {
+            return Err(Error::NoPendingClawback);
         }
 
-        let remaining = grant
+        let now = env.ledger().timestamp();
+        if now < clawback.executable_at {
+            return Err(Error::ClawbackTimelockActive);
+        }
+
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
+        update_share_balances(&env, &mut grant)?;
+
+        // A dispute split may have paid out the held funds in the meantime.
+        let amount = if clawback.amount < grant.claimable {
+            clawback.amount
+        } else {
+            grant.claimable
+        };
+
+        grant.claimable = grant
+            .claimable
+            .checked_sub(amount)
+            .ok_or(Error::MathOverflow)?;
+        let total_amount = grant
             .total_amount
-            .checked_sub(grant.withdrawn)
+            .checked_sub(amount)
             .ok_or(Error::MathOverflow)?;
-
-        grant.flow_rate = 0;
-        grant.status = GrantStatus::Cancelled;
+        lower_total_amount(&mut grant, total_amount);
+        debit_share_balances(&mut grant, amount)?;
+        grant.pending_clawback = PendingClawback::default();
+        if grant.status == GrantStatus::Active && grant.withdrawn == grant.total_amount {
+            grant.status = GrantStatus::Completed;
+        }
         write_grant(&env, grant_id, &grant);
 
-        if remaining > 0 {
-            let contract = env.current_contract_address();
-            let token = read_grant_token(&env)?;
-            let treasury = read_treasury(&env)?;
-            let client = token::Client::new(&env, &token);
-            client.transfer(&contract, &treasury, remaining);
+        let treasury = read_treasury(&env)?;
+        if amount > 0 {
+            let client = token::Client::new(&env, &grant.token);
+            client.transfer(&env.current_contract_address(), &treasury, &amount);
         }
 
-        Ok(())
+        env.events().publish(
+            (symbol_short!("clawback"), grant_id),
+            (amount, clawback.reason_code, treasury),
+        );
+
+        Ok(amount)
+    }
+
+    /// Admin-only. Drop a pending clawback, releasing the held funds to the grantee.
+    pub fn cancel_clawback(env: Env, grant_id: u64) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        let clawback = core::mem::take(&mut grant.pending_clawback);
+        if clawback.amount == 0 {
+            return Err(Error::NoPendingClawback);
+        }
         write_grant(&env, grant_id, &grant);
+
+        env.events().publish(
+            (symbol_short!("clawcncl"), grant_id),
+            (clawback.amount, clawback.reason_code),
+        );
+
         Ok(())
     }
 
-    pub fn propose_rate_change(env: Env, grant_id: u64, new_rate: i128) -> Result<(), Error> {
+    /// Admin-only. Replace the set of addresses allowed to resolve disputes.
+    pub fn set_arbitrators(env: Env, arbitrators: Vec<Address>) -> Result<(), Error> {
         require_admin_auth(&env)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::Arbitrators, &arbitrators);
+        Ok(())
+    }
 
-        if new_rate < 0 {
-            return Err(Error::InvalidRate);
-        }
+    pub fn arbitrators(env: Env) -> Vec<Address> {
+        read_arbitrators(&env)
+    }
+
+    pub fn propose_rate_change(
+        env: Env,
+        grant_id: u64,
+        new_rate: i128,
+        justification: Option<BytesN<32>>,
+    ) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        change_rate(&env, grant_id, new_rate, justification)
+    }
 
+    /// Grantee-only. Accept a pending rate decrease before its notice period ends.
+    pub fn accept_rate_decrease(env: Env, grant_id: u64) -> Result<(), Error> {
         let mut grant = read_grant(&env, grant_id)?;
         if grant.status != GrantStatus::Active {
             return Err(Error::InvalidState);
         }
+        ensure_not_disputed(&grant)?;
+
+        grant.recipient.require_auth();
 
         let now = env.ledger().timestamp();
-        settle_grant(&mut grant, now)?;
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
 
-        if grant.status != GrantStatus::Active {
+        if grant.effective_timestamp == 0 || grant.pending_rate >= grant.flow_rate {
             write_grant(&env, grant_id, &grant);
-            return Err(Error::InvalidState);
+            return Err(Error::NoPendingRateChange);
         }
 
         let old_rate = grant.flow_rate;
+        grant.flow_rate = grant.pending_rate;
+        grant.rate_updated_at = now;
+        grant.pending_rate = 0;
+        grant.effective_timestamp = 0;
+        grant.pending_justification = None;
 
-        if new_rate > grant.flow_rate {
-            grant.pending_rate = new_rate;
-            grant.effective_timestamp = now
-                .checked_add(RATE_INCREASE_TIMELOCK_SECS)
-                .ok_or(Error::MathOverflow)?;
+        write_grant(&env, grant_id, &grant);
 
-            write_grant(&env, grant_id, &grant);
+        env.events().publish(
+            (symbol_short!("rateeff"), grant_id),
+            (old_rate, grant.flow_rate, now),
+        );
 
-            env.events().publish(
-                (symbol_short!("rateprop"), grant_id),
-                (old_rate, new_rate, grant.effective_timestamp),
-            );
+        Ok(())
+    }
+
+    /// Admin-only. How long a rate decrease waits before applying, giving the grantee notice.
+    pub fn set_rate_decrease_notice(env: Env, notice_secs: u64) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::RateDecreaseNotice, &notice_secs);
+        Ok(())
+    }
+
+    pub fn rate_decrease_notice(env: Env) -> u64 {
+        read_rate_decrease_notice(&env)
+    }
+
+    /// Admin-only. Withdraw a timelocked rate change before it takes effect.
+    pub fn cancel_pending_rate(env: Env, grant_id: u64) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+        ensure_not_disputed(&grant)?;
+
+        // Settle first: a proposal whose timelock already elapsed is no longer pending.
+        settle_grant_and_notify(&env, grant_id, &mut grant, env.ledger().timestamp())?;
 
-            return Ok(());
+        if grant.effective_timestamp == 0 {
+            write_grant(&env, grant_id, &grant);
+            return Err(Error::NoPendingRateChange);
         }
 
-        grant.flow_rate = new_rate;
-        grant.rate_updated_at = now;
+        let cancelled_rate = grant.pending_rate;
+        let cancelled_effective = grant.effective_timestamp;
         grant.pending_rate = 0;
         grant.effective_timestamp = 0;
+        grant.pending_justification = None;
 
         write_grant(&env, grant_id, &grant);
 
         env.events().publish(
-            (symbol_short!("rateupdt"), grant_id),
-            (old_rate, new_rate, grant.rate_updated_at),
+            (symbol_short!("ratecncl"), grant_id),
+            (cancelled_rate, cancelled_effective),
         );
 
         Ok(())
     }
 
+    /// The pending timelocked rate change, if any, with the time left until it applies.
+    pub fn pending_rate_change(
+        env: Env,
+        grant_id: u64,
+    ) -> Result<Option<PendingRateChange>, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        let preview = preview_grant_at_now(&env, &grant)?;
+
+        if preview.effective_timestamp == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(PendingRateChange {
+            current_rate: preview.flow_rate,
+            pending_rate: preview.pending_rate,
+            effective_timestamp: preview.effective_timestamp,
+            seconds_remaining: preview
+                .effective_timestamp
+                .saturating_sub(env.ledger().timestamp()),
+            justification: preview.pending_justification,
+        }))
+    }
+
     pub fn update_rate(env: Env, grant_id: u64, new_rate: i128) -> Result<(), Error> {
-        Self::propose_rate_change(env, grant_id, new_rate)
+        Self::propose_rate_change(env, grant_id, new_rate, None)
     }
+
     /// Emergency function: DAO Admin can reassign a grantee's recipient address.
-/// Strictly restricted to the Admin — grantees have zero access to this.
-/// Intended only for key-loss recovery scenarios.
-///
-/// # Arguments
-/// * `grant_id` — the grant whose recipient is being replaced
-/// * `old`      — must match the currently stored recipient (prevents accidental
-///                overwrites when multiple admins race on the same grant)
-/// * `new`      — the replacement address that will own all future withdrawals
+    /// Strictly restricted to the Admin — grantees have zero access to this.
+    /// Intended only for key-loss recovery scenarios.
+    ///
+    /// # Arguments
+    /// * `grant_id` — the grant whose recipient is being replaced
+    /// * `old`      — must match the currently stored recipient (prevents accidental
+    ///   overwrites when multiple admins race on the same grant)
+    /// * `new`      — the replacement address that will own all future withdrawals
     pub fn reassign_grantee(
         env: Env,
         grant_id: u64,
@@ -624,12 +3632,22 @@ impl GrantContract {
         }
 
         grant.recipient = new.clone();
+        // The old recipient's delegate and hook must not apply to the new one.
+        grant.claim_delegate = None;
+        grant.payment_hook = PaymentHook::Off;
         write_grant(&env, grant_id, &grant);
+        remove_recipient_grant(&env, &old, grant_id);
+        add_recipient_grant(&env, &new, grant_id);
 
         env.events().publish(
             (symbol_short!("reasign"), grant_id),
             (old, new, env.ledger().timestamp()),
-    /// Rescue stray tokens sent directly to the contract. Admin-only. Ensures contract_balance - amount >= total_allocated_funds for the grant token.
+        );
+
+        Ok(())
+    }
+
+    /// Rescue stray tokens sent directly to the contract. Admin-only. Ensures contract_balance - amount >= total_allocated_funds for the rescued token.
     pub fn rescue_tokens(
         env: Env,
         token_address: Address,
@@ -646,11 +3664,7 @@ impl GrantContract {
         let client = token::Client::new(&env, &token_address);
         let contract_balance = client.balance(&contract);
 
-        let total_allocated = if token_address == read_grant_token(&env)? {
-            total_allocated_funds(&env)?
-        } else {
-            0
-        };
+        let total_allocated = total_allocated_funds(&env, &token_address)?;
 
         let after_rescue = contract_balance
             .checked_sub(amount)
@@ -659,9 +3673,9 @@ impl GrantContract {
             return Err(Error::RescueWouldViolateAllocated);
         }
 
-        client.transfer(&contract, &to, amount);
-    pub fn update_rate(env: Env, grant_id: u64, new_rate: i128) -> Result<(), Error> {
-        Self::propose_rate_change(env, grant_id, new_rate)
+        client.transfer(&contract, &to, &amount);
+
+        Ok(())
     }
 
     pub fn apply_kpi_multiplier(env: Env, grant_id: u64, multiplier: i128) -> Result<(), Error> {
@@ -675,9 +3689,11 @@ impl GrantContract {
         if grant.status != GrantStatus::Active {
             return Err(Error::InvalidState);
         }
+        ensure_not_disputed(&grant)?;
+        ensure_no_active_subgrants(&env, &grant)?;
 
         let now = env.ledger().timestamp();
-        settle_grant(&mut grant, now)?;
+        settle_grant_and_notify(&env, grant_id, &mut grant, now)?;
 
         if grant.status != GrantStatus::Active {
             write_grant(&env, grant_id, &grant);
@@ -698,6 +3714,19 @@ impl GrantContract {
                 .ok_or(Error::MathOverflow)?;
         }
 
+        let mut scaled_schedule = Vec::new(&env);
+        for segment in grant.rate_schedule.iter() {
+            let rate = segment
+                .rate
+                .checked_mul(multiplier)
+                .ok_or(Error::MathOverflow)?;
+            scaled_schedule.push_back(RateSegment {
+                start: segment.start,
+                rate,
+            });
+        }
+        grant.rate_schedule = scaled_schedule;
+
         write_grant(&env, grant_id, &grant);
 
         env.events().publish(
@@ -709,4 +3738,5 @@ impl GrantContract {
     }
 }
 
+#[cfg(test)]
 mod test;
@@ -1,5 +1,3 @@
-#![no_std]
-
 use soroban_sdk::{
     contract, contracterror, contractimpl, contracttype, symbol_short, Address, Env,
 };
@@ -71,7 +69,7 @@ pub enum Error {
     InvalidStatusTransition = 10, // New error for invalid status transitions
 }
 
-fn read_admin(env: &Env) -> Result<Address, Error> {
+pub(crate) fn read_admin(env: &Env) -> Result<Address, Error> {
     env.storage()
         .instance()
         .get(&DataKey::Admin)
@@ -84,14 +82,14 @@ fn require_admin_auth(env: &Env) -> Result<(), Error> {
     Ok(())
 }
 
-fn read_grant(env: &Env, grant_id: u64) -> Result<Grant, Error> {
+pub(crate) fn read_grant(env: &Env, grant_id: u64) -> Result<Grant, Error> {
     env.storage()
         .instance()
         .get(&DataKey::Grant(grant_id))
         .ok_or(Error::GrantNotFound)
 }
 
-fn write_grant(env: &Env, grant_id: u64, grant: &Grant) {
+pub(crate) fn write_grant(env: &Env, grant_id: u64, grant: &Grant) {
     env.storage().instance().set(&DataKey::Grant(grant_id), grant);
 }
 
@@ -129,7 +127,7 @@ fn validate_status_transition(current_mask: u32, new_mask: u32) -> Result<(), Er
     }
 }
 
-fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
+pub(crate) fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
     if now < grant.last_update_ts {
         return Err(Error::InvalidState);
     }
@@ -255,7 +253,8 @@ impl GrantContract {
         let mut grant = read_grant(&env, grant_id)?;
 
         let current_mask = grant.status_mask;
-        let new_mask = set_status(current_mask, STATUS_CANCELLED);
+        let mut new_mask = set_status(current_mask, STATUS_CANCELLED);
+        new_mask = clear_status(new_mask, STATUS_ACTIVE | STATUS_PAUSED);
         
         // Validate transition
         validate_status_transition(current_mask, new_mask)?;
@@ -279,7 +278,7 @@ impl GrantContract {
             return Err(Error::InvalidState);
         }
 
-        let new_mask = set_status(current_mask, STATUS_PAUSED);
+        let mut new_mask = set_status(current_mask, STATUS_PAUSED);
         new_mask = clear_status(new_mask, STATUS_ACTIVE);
 
         settle_grant(&mut grant, env.ledger().timestamp())?;
@@ -300,7 +299,7 @@ impl GrantContract {
             return Err(Error::InvalidState);
         }
 
-        let new_mask = set_status(current_mask, STATUS_ACTIVE);
+        let mut new_mask = set_status(current_mask, STATUS_ACTIVE);
         new_mask = clear_status(new_mask, STATUS_PAUSED);
 
         settle_grant(&mut grant, env.ledger().timestamp())?;
@@ -379,8 +378,14 @@ impl GrantContract {
 
         let mut grant = read_grant(&env, grant_id)?;
 
-        // Can only withdraw from active grants
-        if !has_status(grant.status_mask, STATUS_ACTIVE) {
+        // Balance accrued before a grant was paused, completed or cancelled stays
+        // withdrawable; settle_grant only adds to it while the grant is active.
+        // An emergency pause freezes withdrawals as well.
+        let withdrawable_mask =
+            STATUS_ACTIVE | STATUS_PAUSED | STATUS_COMPLETED | STATUS_CANCELLED;
+        if grant.status_mask & withdrawable_mask == 0
+            || has_status(grant.status_mask, STATUS_EMERGENCY_PAUSE)
+        {
             return Err(Error::InvalidState);
         }
 
@@ -406,12 +411,13 @@ impl GrantContract {
             .checked_add(grant.claimable)
             .ok_or(Error::MathOverflow)?;
 
-        if accounted == grant.total_amount {
+        if accounted == grant.total_amount && has_status(grant.status_mask, STATUS_ACTIVE) {
             grant.status_mask = set_status(grant.status_mask, STATUS_COMPLETED);
             grant.status_mask = clear_status(grant.status_mask, STATUS_ACTIVE);
         }
 
         write_grant(&env, grant_id, &grant);
+        Ok(())
     }
 
     pub fn update_rate(env: Env, grant_id: u64, new_rate: i128) -> Result<(), Error> {
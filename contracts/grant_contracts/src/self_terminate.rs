@@ -1,11 +1,8 @@
-#![no_std]
-
-use soroban_sdk::{
-    contract, contracterror, contractimpl, contracttype, symbol_short, Address, Env,
-};
+use soroban_sdk::{contracterror, contracttype, symbol_short, Env, String};
 
+pub use super::optimized::GrantContract;
 use super::optimized::{
-    GrantContract, Grant, Error, DataKey, read_grant, write_grant, settle_grant,
+    Grant, Error, read_grant, write_grant, settle_grant,
     STATUS_ACTIVE, STATUS_PAUSED, STATUS_COMPLETED, STATUS_CANCELLED,
     has_status, set_status, clear_status, read_admin,
 };
@@ -85,6 +82,10 @@ impl GrantContract {
             SelfTerminateResult::refund_to_admin(&env, remaining_balance)?;
         }
         
+        // The final claimable amount has been paid out above
+        grant.withdrawn = total_accounted;
+        grant.claimable = 0;
+
         // Update grant status
         grant.status_mask = set_status(grant.status_mask, STATUS_SELF_TERMINATED);
         grant.status_mask = clear_status(grant.status_mask, STATUS_ACTIVE);
@@ -100,7 +101,7 @@ impl GrantContract {
             final_claimable,
             refunded_amount: remaining_balance,
             terminated_at: env.ledger().timestamp(),
-            termination_reason: "Self-terminated by grantee".to_string(),
+            termination_reason: String::from_str(&env, "Self-terminated by grantee"),
         };
         
         // Emit termination event
@@ -139,7 +140,7 @@ impl GrantContract {
             final_claimable: grant.claimable,
             refunded_amount: remaining_balance,
             terminated_at: grant.rate_updated_at, // Use as approximation
-            termination_reason: "Self-terminated by grantee".to_string(),
+            termination_reason: String::from_str(&env, "Self-terminated by grantee"),
         })
     }
     
@@ -204,7 +205,7 @@ impl SelfTerminateResult {
         // TODO: Implement actual token transfer logic
         
         env.events().publish(
-            (symbol_short!("grantee_settle"), grant.recipient.clone()),
+            (symbol_short!("gsettle"), grant.recipient.clone()),
             (amount, "Final claimable amount settled"),
         );
         
@@ -224,7 +225,7 @@ impl SelfTerminateResult {
         // TODO: Implement actual token transfer logic
         
         env.events().publish(
-            (symbol_short!("admin_refund"), admin),
+            (symbol_short!("arefund"), admin),
             (amount, "Unspent grant balance refunded"),
         );
         
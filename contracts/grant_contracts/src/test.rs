@@ -1,9 +1,15 @@
 #![cfg(test)]
 
-use super::{Error, GrantContract, GrantContractClient, GrantStatus, SCALING_FACTOR};
+use super::{
+    DataKey, Dispute, DisputeResolution, Error, FeePolicy, GrantContract, GrantContractClient,
+    GrantParams, GrantStatus, GrantWithdrawal, PaymentHook, PendingClawback, PendingRateChange,
+    ProgramDefaults, QfMatch, RatePeriod, RateSegment, RecipientShare, WarmupCurve, WarmupShape,
+    DEFAULT_WARMUP_CURVE, SCALING_FACTOR,
+};
 use soroban_sdk::{
-    testutils::{Address as _, AuthorizedFunction, Ledger},
-    Address, Env, InvokeError,
+    testutils::{storage::Persistent as _, Address as _, AuthorizedFunction, IssuerFlags, Ledger},
+    token::{StellarAssetClient, TokenClient},
+    vec, Address, BytesN, Env, InvokeError, String,
 };
 
 const RATE_INCREASE_TIMELOCK_SECS: u64 = 48 * 60 * 60;
@@ -14,6 +20,21 @@ fn set_timestamp(env: &Env, timestamp: u64) {
     });
 }
 
+/// Registers and initializes a contract at t=0, returning it with its admin and treasury.
+fn setup_contract(env: &Env) -> (GrantContractClient<'_>, Address, Address) {
+    let admin = Address::generate(env);
+    let treasury = Address::generate(env);
+    let oracle = Address::generate(env);
+
+    let client = GrantContractClient::new(env, &env.register(GrantContract, ()));
+    set_timestamp(env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &treasury, &oracle);
+
+    (client, admin, treasury)
+}
+
 fn assert_contract_error<T, C>(
     result: Result<Result<T, C>, Result<Error, InvokeError>>,
     expected: Error,
@@ -24,136 +45,112 @@ fn assert_contract_error<T, C>(
 #[test]
 fn test_propose_rate_change_sets_pending_rate_and_effective_timestamp() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 1;
-    // Flow rates are now scaled by SCALING_FACTOR
-    let rate_1: i128 = 10 * SCALING_FACTOR;
-    let rate_2: i128 = 25 * SCALING_FACTOR;
-
     set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client.mock_all_auths().initialize(&admin, &oracle);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &10_000, &rate_1, &0);
-        .create_grant(&grant_id, &recipient, &50_000_000, &10);
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        50_000_000,
+        10 * SCALING_FACTOR,
+    );
 
     set_timestamp(&env, 1_100);
-    client.mock_all_auths().propose_rate_change(&grant_id, &25);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&grant_id, &(25 * SCALING_FACTOR), &None);
 
+    let effective_timestamp = 1_100 + RATE_INCREASE_TIMELOCK_SECS;
     let grant = client.get_grant(&grant_id);
     assert_eq!(grant.claimable, 1_000);
-    assert_eq!(grant.flow_rate, 10);
-    assert_eq!(grant.pending_rate, 25);
-    assert_eq!(
-        grant.effective_timestamp,
-        1_100 + RATE_INCREASE_TIMELOCK_SECS
-    );
+    assert_eq!(grant.flow_rate, 10 * SCALING_FACTOR);
+    assert_eq!(grant.pending_rate, 25 * SCALING_FACTOR);
+    assert_eq!(grant.effective_timestamp, effective_timestamp);
     assert_eq!(grant.last_update_ts, 1_100);
 
-    let just_before_activation = grant.effective_timestamp - 1;
-    set_timestamp(&env, just_before_activation);
-    let expected = 1_000 + (i128::from(just_before_activation - 1_100) * 10);
+    // The old rate keeps applying until the timelock expires.
+    set_timestamp(&env, effective_timestamp - 1);
+    let expected = 1_000 + i128::from(effective_timestamp - 1 - 1_100) * 10;
     assert_eq!(client.claimable(&grant_id), expected);
 }
 
 #[test]
 fn test_withdraw_respects_timelock_for_rate_increases() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = setup_funded_token(&env, &admin, &client.address, 5_000_000);
 
     let grant_id: u64 = 2;
+    create_simple_grant(&client, grant_id, &recipient, &token, 5_000_000, SCALING_FACTOR);
 
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &oracle);
+    set_timestamp(&env, 10);
     client
         .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000_000, &1);
-    // 100 seconds * 10 tokens/sec = 1000 tokens
-    assert_eq!(client.claimable(&grant_id), 1_000);
-
-    set_timestamp(&env, 10);
-    client.mock_all_auths().propose_rate_change(&grant_id, &5);
+        .propose_rate_change(&grant_id, &(5 * SCALING_FACTOR), &None);
 
     set_timestamp(&env, 20);
     assert_eq!(client.claimable(&grant_id), 20);
-
     assert_contract_error(
         client.mock_all_auths().try_withdraw(&grant_id, &21),
         Error::InvalidAmount,
     );
     client.mock_all_auths().withdraw(&grant_id, &20);
+    assert_eq!(client.claimable(&grant_id), 0);
 
     let effective_timestamp = 10 + RATE_INCREASE_TIMELOCK_SECS;
     set_timestamp(&env, effective_timestamp - 1);
-    let before_effective = i128::from((effective_timestamp - 1) - 20);
-    assert_eq!(client.claimable(&grant_id), before_effective);
+    assert_eq!(
+        client.claimable(&grant_id),
+        i128::from(effective_timestamp - 1 - 20)
+    );
 
     set_timestamp(&env, effective_timestamp + 10);
-    let after_effective = i128::from(effective_timestamp - 20) + (10 * 5);
-    assert_eq!(client.claimable(&grant_id), after_effective);
-    set_timestamp(&env, 1_140);
-    // 1000 + (40 seconds * 25 tokens/sec) = 1000 + 1000 = 2000
-    assert_eq!(client.claimable(&grant_id), 1_000 + 40 * 25);
-
-    client.mock_all_auths().withdraw(&grant_id, &700);
-    assert_eq!(client.claimable(&grant_id), 1_000 + 40 * 25 - 700);
-
-    set_timestamp(&env, 1_150);
-    // 1000 + (50 seconds * 25 tokens/sec) - 700 = 1000 + 1250 - 700 = 1550
-    assert_eq!(client.claimable(&grant_id), 1_000 + 50 * 25 - 700);
+    assert_eq!(
+        client.claimable(&grant_id),
+        i128::from(effective_timestamp - 20) + 50
+    );
 }
 
 #[test]
-fn test_withdraw_respects_timelock_for_rate_increases() {
 fn test_propose_rate_change_decrease_applies_immediately_and_clears_pending() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 3;
-
-    set_timestamp(&env, 100);
-    client.mock_all_auths().initialize(&admin, &grant_token);
     set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin, &oracle);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &1_000, &5, &0);
-        .create_grant(&grant_id, &recipient, &50_000_000, &10);
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        50_000_000,
+        10 * SCALING_FACTOR,
+    );
 
     set_timestamp(&env, 1_100);
-    client.mock_all_auths().propose_rate_change(&grant_id, &20);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&grant_id, &(20 * SCALING_FACTOR), &None);
 
     set_timestamp(&env, 1_200);
-    client.mock_all_auths().propose_rate_change(&grant_id, &4);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&grant_id, &(4 * SCALING_FACTOR), &None);
 
     let grant = client.get_grant(&grant_id);
-    assert_eq!(grant.flow_rate, 4);
+    assert_eq!(grant.claimable, 2_000);
+    assert_eq!(grant.flow_rate, 4 * SCALING_FACTOR);
     assert_eq!(grant.pending_rate, 0);
     assert_eq!(grant.effective_timestamp, 0);
     assert_eq!(grant.rate_updated_at, 1_200);
-    assert_eq!(grant.claimable, 2_000);
 
     set_timestamp(&env, 1_210);
     assert_eq!(client.claimable(&grant_id), 2_040);
@@ -162,947 +159,584 @@ fn test_propose_rate_change_decrease_applies_immediately_and_clears_pending() {
 #[test]
 fn test_propose_rate_change_requires_admin_auth() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 4;
+    create_simple_grant(&client, grant_id, &recipient, &token, 1_000_000, SCALING_FACTOR);
 
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    set_timestamp(&env, 100);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client.mock_all_auths().initialize(&admin, &oracle);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000_000, &1);
-
-    set_timestamp(&env, 10);
-    client.mock_all_auths().propose_rate_change(&grant_id, &5);
-        .create_grant(&grant_id, &recipient, &1_000, &(5 * SCALING_FACTOR));
+    assert!(client
+        .try_propose_rate_change(&grant_id, &(2 * SCALING_FACTOR), &None)
+        .is_err());
 
     client
         .mock_all_auths()
-        .propose_rate_change(&grant_id, &7_i128);
-    client.mock_all_auths().update_rate(&grant_id, &(7 * SCALING_FACTOR));
-
-    set_timestamp(&env, 20);
-    assert_eq!(client.claimable(&grant_id), 20);
-
-    assert_contract_error(
-        client.mock_all_auths().try_withdraw(&grant_id, &21),
-        Error::InvalidAmount,
-    );
-    client.mock_all_auths().withdraw(&grant_id, &20);
-
-    let effective_timestamp = 10 + RATE_INCREASE_TIMELOCK_SECS;
-    set_timestamp(&env, effective_timestamp - 1);
-    let before_effective = i128::from((effective_timestamp - 1) - 20);
-    assert_eq!(client.claimable(&grant_id), before_effective);
-
-    set_timestamp(&env, effective_timestamp + 10);
-    let after_effective = i128::from(effective_timestamp - 20) + (10 * 5);
-    assert_eq!(client.claimable(&grant_id), after_effective);
+        .propose_rate_change(&grant_id, &(2 * SCALING_FACTOR), &None);
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
 }
 
 #[test]
-fn test_propose_rate_change_decrease_applies_immediately_and_clears_pending() {
 fn test_propose_rate_change_rejects_invalid_rate_and_inactive_states() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &oracle);
-
-    set_timestamp(&env, 2_000);
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000, &4, &0);
-        .create_grant(&grant_id, &recipient, &50_000_000, &10);
-
-    set_timestamp(&env, 1_100);
-    client.mock_all_auths().propose_rate_change(&grant_id, &20);
-
-    set_timestamp(&env, 1_200);
-    client.mock_all_auths().propose_rate_change(&grant_id, &4);
-
-    let grant = client.get_grant(&grant_id);
-    assert_eq!(grant.flow_rate, 4);
-    assert_eq!(grant.pending_rate, 0);
-    assert_eq!(grant.effective_timestamp, 0);
-    assert_eq!(grant.rate_updated_at, 1_200);
-    assert_eq!(grant.claimable, 2_000);
+    let token = setup_funded_token(&env, &admin, &client.address, 100);
 
-    set_timestamp(&env, 1_210);
-    assert_eq!(client.claimable(&grant_id), 2_040);
-    set_timestamp(&env, 2_000);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    let negative_rate_grant: u64 = 5;
-    client
-        .mock_all_auths()
-        .create_grant(&negative_rate_grant, &recipient, &1_000, &5);
+    create_simple_grant(&client, 5, &recipient, &token, 1_000_000, SCALING_FACTOR);
     assert_contract_error(
         client
             .mock_all_auths()
-            .try_propose_rate_change(&negative_rate_grant, &-1_i128),
+            .try_propose_rate_change(&5, &-1, &None),
         Error::InvalidRate,
     );
 
-    let cancelled_grant: u64 = 6;
-    client
-        .mock_all_auths()
-        .create_grant(&cancelled_grant, &recipient, &1_000, &5);
-    client.mock_all_auths().cancel_grant(&cancelled_grant);
+    create_simple_grant(&client, 6, &recipient, &token, 1_000_000, SCALING_FACTOR);
+    client.mock_all_auths().cancel_grant(&6);
     assert_contract_error(
         client
             .mock_all_auths()
-            .try_propose_rate_change(&cancelled_grant, &8_i128),
+            .try_propose_rate_change(&6, &(2 * SCALING_FACTOR), &None),
         Error::InvalidState,
     );
 
-    let completed_grant: u64 = 7;
-    client
-        .mock_all_auths()
-        .create_grant(&completed_grant, &recipient, &100, &10);
+    create_simple_grant(&client, 7, &recipient, &token, 100, 10 * SCALING_FACTOR);
     set_timestamp(&env, 10);
-    client.mock_all_auths().withdraw(&completed_grant, &100);
-
-    let completed = client.get_grant(&completed_grant);
-    assert_eq!(completed.status, GrantStatus::Completed);
-
+    client.mock_all_auths().withdraw(&7, &100);
+    assert_eq!(client.get_grant(&7).status, GrantStatus::Completed);
     assert_contract_error(
         client
             .mock_all_auths()
-            .try_propose_rate_change(&completed_grant, &4_i128),
+            .try_propose_rate_change(&7, &(2 * SCALING_FACTOR), &None),
         Error::InvalidState,
     );
-        .create_grant(&grant_id, &recipient, &5_000, &(4 * SCALING_FACTOR));
-
-    client.mock_all_auths().update_rate(&grant_id, &(9 * SCALING_FACTOR));
-
-    let grant = client.get_grant(&grant_id);
-    assert_eq!(grant.claimable, 0);
-    assert_eq!(grant.flow_rate, 9 * SCALING_FACTOR);
-    assert_eq!(grant.last_update_ts, 2_000);
-
-    set_timestamp(&env, 2_010);
-    // 10 seconds * 9 tokens/sec = 90 tokens
-    assert_eq!(client.claimable(&grant_id), 90);
 }
 
 #[test]
-fn test_propose_rate_change_requires_admin_auth() {
 fn test_update_rate_uses_timelocked_behavior() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 8;
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        5_000_000,
+        2 * SCALING_FACTOR,
+    );
 
     set_timestamp(&env, 10);
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    set_timestamp(&env, 100);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &10_000, &3, &0);
-        .create_grant(&grant_id, &recipient, &1_000, &5);
-    set_timestamp(&env, 10);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &oracle);
     client
         .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000_000, &2);
-
-    set_timestamp(&env, 10);
-    client.mock_all_auths().update_rate(&grant_id, &6);
+        .update_rate(&grant_id, &(6 * SCALING_FACTOR));
 
     let grant = client.get_grant(&grant_id);
-    assert_eq!(grant.flow_rate, 2);
-    assert_eq!(grant.pending_rate, 6);
+    assert_eq!(grant.flow_rate, 2 * SCALING_FACTOR);
+    assert_eq!(grant.pending_rate, 6 * SCALING_FACTOR);
     assert_eq!(grant.effective_timestamp, 10 + RATE_INCREASE_TIMELOCK_SECS);
 
     set_timestamp(&env, 20);
     assert_eq!(client.claimable(&grant_id), 40);
-        .create_grant(&grant_id, &recipient, &10_000, &(3 * SCALING_FACTOR));
-
-    set_timestamp(&env, 20);
-    client.mock_all_auths().update_rate(&grant_id, &(5 * SCALING_FACTOR));
-
-    set_timestamp(&env, 40);
-    client.mock_all_auths().update_rate(&grant_id, &(2 * SCALING_FACTOR));
-
-    set_timestamp(&env, 70);
-    assert_eq!(client.claimable(&grant_id), 30 + 100 + 60);
 }
 
 #[test]
 fn test_apply_kpi_multiplier_requires_oracle_auth() {
     let env = Env::default();
     let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
     let oracle = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 9;
-
-    client
-        .mock_all_auths()
-        .propose_rate_change(&grant_id, &7_i128);
+    let token = Address::generate(&env);
 
-    let auths = env.auths();
-    assert_eq!(auths.len(), 1);
-    assert_eq!(auths[0].0, admin);
-    set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    let client = GrantContractClient::new(&env, &env.register(GrantContract, ()));
     set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &oracle);
     client
         .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000_000, &2);
+        .initialize(&admin, &treasury, &oracle);
+
+    let grant_id: u64 = 9;
+    create_simple_grant(&client, grant_id, &recipient, &token, 1_000_000, SCALING_FACTOR);
 
     set_timestamp(&env, 10);
-    client.mock_all_auths().apply_kpi_multiplier(&grant_id, &2);
+    assert!(client.try_apply_kpi_multiplier(&grant_id, &2).is_err());
 
+    client.mock_all_auths().apply_kpi_multiplier(&grant_id, &2);
     let auths = env.auths();
     assert_eq!(auths.len(), 1);
     assert_eq!(auths[0].0, oracle);
     assert!(matches!(
         auths[0].1.function,
-        AuthorizedFunction::Contract((_, _, _))
+        AuthorizedFunction::Contract(_)
     ));
 }
 
 #[test]
 fn test_apply_kpi_multiplier_settles_before_updating_rate() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 10;
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &oracle);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &20_000, &4, &0);
-        .create_grant(&grant_id, &recipient, &50_000_000, &10);
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        50_000_000,
+        10 * SCALING_FACTOR,
+    );
 
     set_timestamp(&env, 100);
     client.mock_all_auths().apply_kpi_multiplier(&grant_id, &3);
 
     let grant = client.get_grant(&grant_id);
     assert_eq!(grant.claimable, 1_000);
-    assert_eq!(grant.flow_rate, 30);
+    assert_eq!(grant.flow_rate, 30 * SCALING_FACTOR);
     assert_eq!(grant.last_update_ts, 100);
 
     set_timestamp(&env, 110);
     assert_eq!(client.claimable(&grant_id), 1_300);
-        .create_grant(&grant_id, &recipient, &20_000, &(4 * SCALING_FACTOR));
-
-    set_timestamp(&env, 1_050);
-    client.mock_all_auths().update_rate(&grant_id, &0);
-    assert_eq!(client.claimable(&grant_id), 200);
-
-    set_timestamp(&env, 1_250);
-    assert_eq!(client.claimable(&grant_id), 200);
-
-    client.mock_all_auths().update_rate(&grant_id, &(6 * SCALING_FACTOR));
-
-    set_timestamp(&env, 1_300);
-    assert_eq!(client.claimable(&grant_id), 200 + 50 * 6);
 }
 
 #[test]
-fn test_propose_rate_change_rejects_invalid_rate_and_inactive_states() {
 fn test_apply_kpi_multiplier_rejects_invalid_multiplier_and_inactive_states() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client.mock_all_auths().initialize(&admin, &oracle);
+    let token = Address::generate(&env);
 
-    let negative_rate_grant: u64 = 5;
-    let invalid_multiplier_grant: u64 = 11;
-    client
-        .mock_all_auths()
-        .create_grant(&negative_rate_grant, &recipient, &1_000, &5, &0);
-        .create_grant(&invalid_multiplier_grant, &recipient, &1_000, &5);
-        .create_grant(&negative_rate_grant, &recipient, &1_000, &(5 * SCALING_FACTOR));
+    create_simple_grant(&client, 11, &recipient, &token, 1_000_000, SCALING_FACTOR);
     assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_propose_rate_change(&negative_rate_grant, &-1_i128),
-        Error::InvalidRate,
-    );
-
-    let cancelled_grant: u64 = 6;
-            .try_apply_kpi_multiplier(&invalid_multiplier_grant, &0_i128),
+        client.mock_all_auths().try_apply_kpi_multiplier(&11, &0),
         Error::InvalidRate,
     );
     assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_apply_kpi_multiplier(&invalid_multiplier_grant, &-1_i128),
+        client.mock_all_auths().try_apply_kpi_multiplier(&11, &-1),
         Error::InvalidRate,
     );
 
-    let cancelled_grant: u64 = 12;
-    client
-        .mock_all_auths()
-        .create_grant(&cancelled_grant, &recipient, &1_000, &5, &0);
-        .create_grant(&cancelled_grant, &recipient, &1_000, &(5 * SCALING_FACTOR));
-    client.mock_all_auths().cancel_grant(&cancelled_grant);
+    create_simple_grant(&client, 12, &recipient, &token, 1_000_000, SCALING_FACTOR);
+    client.mock_all_auths().cancel_grant(&12);
     assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_propose_rate_change(&cancelled_grant, &8_i128),
-        Error::InvalidState,
-    );
-
-    let completed_grant: u64 = 7;
-            .try_apply_kpi_multiplier(&cancelled_grant, &2_i128),
+        client.mock_all_auths().try_apply_kpi_multiplier(&12, &2),
         Error::InvalidState,
     );
 
-    let completed_grant: u64 = 13;
-    client
-        .mock_all_auths()
-        .create_grant(&completed_grant, &recipient, &100, &10, &0);
-        .create_grant(&completed_grant, &recipient, &100, &(10 * SCALING_FACTOR));
+    // Fully accrued by t=10, so settling completes the grant.
+    create_simple_grant(&client, 13, &recipient, &token, 100, 10 * SCALING_FACTOR);
     set_timestamp(&env, 10);
-    // 10 seconds * 10 tokens/sec = 100 tokens (full amount)
-    client.mock_all_auths().withdraw(&completed_grant, &100);
-
-    let completed = client.get_grant(&completed_grant);
-    assert_eq!(completed.status, GrantStatus::Completed);
-
     assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_propose_rate_change(&completed_grant, &4_i128),
-            .try_apply_kpi_multiplier(&completed_grant, &2_i128),
+        client.mock_all_auths().try_apply_kpi_multiplier(&13, &2),
         Error::InvalidState,
     );
 }
 
 #[test]
-fn test_update_rate_uses_timelocked_behavior() {
 fn test_apply_kpi_multiplier_scales_pending_rate_and_preserves_accrual_boundaries() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let oracle = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
-    let grant_id: u64 = 8;
     let grant_id: u64 = 14;
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        500_000_000,
+        10 * SCALING_FACTOR,
+    );
 
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client.mock_all_auths().initialize(&admin, &oracle);
+    set_timestamp(&env, 100);
     client
         .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &1_000, &10, &0);
-        .create_grant(&grant_id, &recipient, &5_000_000, &2);
-        .create_grant(&grant_id, &recipient, &500_000_000, &10);
-
-    set_timestamp(&env, 100);
-    client.mock_all_auths().propose_rate_change(&grant_id, &20);
-        .create_grant(&grant_id, &recipient, &1_000, &(10 * SCALING_FACTOR));
-
-    set_timestamp(&env, 20);
-    client.mock_all_auths().update_rate(&grant_id, &(5 * SCALING_FACTOR));
-
-    set_timestamp(&env, 60);
-    assert_eq!(client.claimable(&grant_id), 400);
+        .propose_rate_change(&grant_id, &(20 * SCALING_FACTOR), &None);
+    let effective_timestamp = 100 + RATE_INCREASE_TIMELOCK_SECS;
 
     set_timestamp(&env, 150);
     client.mock_all_auths().apply_kpi_multiplier(&grant_id, &2);
 
     let grant = client.get_grant(&grant_id);
     assert_eq!(grant.claimable, 1_500);
-    assert_eq!(grant.flow_rate, 20);
-    assert_eq!(grant.pending_rate, 40);
+    assert_eq!(grant.flow_rate, 20 * SCALING_FACTOR);
+    assert_eq!(grant.pending_rate, 40 * SCALING_FACTOR);
+    assert_eq!(grant.effective_timestamp, effective_timestamp);
     assert_eq!(grant.last_update_ts, 150);
+
+    set_timestamp(&env, effective_timestamp - 1);
     assert_eq!(
-        grant.effective_timestamp,
-        100 + RATE_INCREASE_TIMELOCK_SECS
+        client.claimable(&grant_id),
+        1_500 + i128::from(effective_timestamp - 1 - 150) * 20
     );
 
-    let just_before_activation = grant.effective_timestamp - 1;
-    set_timestamp(&env, just_before_activation);
-    let expected_before = 1_500 + (i128::from(just_before_activation - 150) * 20);
-    assert_eq!(client.claimable(&grant_id), expected_before);
-
-    set_timestamp(&env, 10);
-    client.mock_all_auths().update_rate(&grant_id, &6);
-
-    let grant = client.get_grant(&grant_id);
-    assert_eq!(grant.flow_rate, 2);
-    assert_eq!(grant.pending_rate, 6);
-    assert_eq!(grant.effective_timestamp, 10 + RATE_INCREASE_TIMELOCK_SECS);
-
-    set_timestamp(&env, 20);
-    assert_eq!(client.claimable(&grant_id), 40);
-}
-
-    set_timestamp(&env, grant.effective_timestamp + 10);
-    let expected_after = 1_500
-        + (i128::from(grant.effective_timestamp - 150) * 20)
-        + (i128::from(10_u64) * 40);
-    assert_eq!(client.claimable(&grant_id), expected_after);
+    set_timestamp(&env, effective_timestamp + 10);
+    assert_eq!(
+        client.claimable(&grant_id),
+        1_500 + i128::from(effective_timestamp - 150) * 20 + 10 * 40
+    );
 }
 
 #[test]
 fn test_rescue_tokens_requires_admin_auth() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let to = Address::generate(&env);
+    let token = setup_funded_token(&env, &admin, &client.address, 1_000);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    assert!(client.try_rescue_tokens(&token, &100, &to).is_err());
 
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    // Calling rescue_tokens without admin auth must fail (auth or NotAuthorized).
-    assert!(client.try_rescue_tokens(&grant_token, &100, &to).is_err());
+    client.mock_all_auths().rescue_tokens(&token, &100, &to);
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
+    assert_eq!(TokenClient::new(&env, &token).balance(&to), 100);
 }
 
 #[test]
 fn test_rescue_tokens_rejects_invalid_amount() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let to = Address::generate(&env);
+    let token = setup_funded_token(&env, &admin, &client.address, 1_000);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
     assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_rescue_tokens(&grant_token, &0, &to),
+        client.mock_all_auths().try_rescue_tokens(&token, &0, &to),
+        Error::InvalidAmount,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_rescue_tokens(&token, &-1, &to),
         Error::InvalidAmount,
     );
 }
 
 #[test]
-fn test_rescue_tokens_requires_admin_auth() {
+fn test_warmup_period_linear_scaling() {
     let env = Env::default();
-    let admin = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let to = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
+    let recipient = Address::generate(&env);
+    let token = Address::generate(&env);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_id: u64 = 100;
+    set_timestamp(&env, 1_000);
+    client.mock_all_auths().create_grant(
+        &grant_id,
+        &recipient,
+        &token,
+        &100_000,
+        &(100 * SCALING_FACTOR),
+        &30,
+        &None,
+        &None,
+    );
 
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    // Calling rescue_tokens without admin auth must fail (auth or NotAuthorized).
-    assert!(client.try_rescue_tokens(&grant_token, &100, &to).is_err());
-}
+    // The default curve ramps linearly from 25% to 100% of the rate, so accrual after x
+    // seconds of a 30-second warmup is rate * (0.25x + 0.75x^2 / 60).
+    set_timestamp(&env, 1_001);
+    assert_eq!(client.claimable(&grant_id), 26);
 
-#[test]
-fn test_rescue_tokens_rejects_invalid_amount() {
-    let env = Env::default();
-    let admin = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let to = Address::generate(&env);
+    set_timestamp(&env, 1_015);
+    assert_eq!(client.claimable(&grant_id), 656);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    set_timestamp(&env, 1_030);
+    assert_eq!(client.claimable(&grant_id), 1_875);
 
-    client.mock_all_auths().initialize(&admin, &grant_token);
-    assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_rescue_tokens(&grant_token, &0, &to),
-        Error::InvalidAmount,
-    );
-    assert_contract_error(
-        client
-            .mock_all_auths()
-            .try_rescue_tokens(&grant_token, &-1_i128, &to),
-        Error::InvalidAmount,
-    );
+    // Past the warmup the full rate applies.
+    set_timestamp(&env, 1_040);
+    assert_eq!(client.claimable(&grant_id), 2_875);
 }
 
-#[test]
-fn test_warmup_period_linear_scaling() {
-/// Tests for low-decimal tokens (Issue #18: High-Precision Flow Rates)
-/// These tests verify that the scaling factor prevents zero flow rates
-/// when dealing with tokens that have few decimal places.
-
 #[test]
 fn test_low_decimal_token_2_decimals_1_year() {
-    // Scenario: 100 tokens with 2 decimals over 1 year
-    // Without scaling: 10000 / 31536000 = 0 (integer division)
-    // With scaling: (10000 * 1e7) / 31536000 = 3170 scaled rate
-    // This allows proper accrual over time
-
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
+    let token = Address::generate(&env);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 100;
-    let flow_rate: i128 = 100; // 100 tokens per second at full rate
-    let warmup_duration: u64 = 30; // 30 seconds warmup
-
-    set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &100_000, &flow_rate, &warmup_duration);
-
-    // At start (t=0 of warmup): should be 25% of flow rate
-    set_timestamp(&env, 1_000);
-    assert_eq!(client.claimable(&grant_id), 0);
-
-    // After 1 second: 25% rate = 25 tokens
-    set_timestamp(&env, 1_001);
-    assert_eq!(client.claimable(&grant_id), 25);
-
-    // At midpoint (t=15): should be ~62.5% of flow rate
-    // 15 seconds at ramping rate
-    set_timestamp(&env, 1_015);
-    let claimable_at_15 = client.claimable(&grant_id);
-    // Expected: roughly 25% for 0s + ramp from 25% to 62.5% over 15s
-    // Approximate: (25 + 62.5) / 2 * 15 = 656.25
-    assert!(claimable_at_15 >= 650 && claimable_at_15 <= 660);
+    // 100.00 units of a 2-decimal token streamed over one year.
+    let total_amount: i128 = 10_000;
+    let year_secs: u64 = 365 * 24 * 60 * 60;
+    let flow_rate = total_amount * SCALING_FACTOR / i128::from(year_secs);
+    create_simple_grant(&client, 104, &recipient, &token, total_amount, flow_rate);
 
-    // After warmup period (t=30): should be at 100% rate
-    set_timestamp(&env, 1_030);
-    let claimable_at_30 = client.claimable(&grant_id);
-    // Expected: average rate over 30s warmup ≈ (25% + 100%) / 2 = 62.5% avg
-    // 30 * 100 * 0.625 = 1875
-    assert!(claimable_at_30 >= 1850 && claimable_at_30 <= 1900);
+    set_timestamp(&env, year_secs / 2);
+    let half = client.claimable(&104);
+    assert!((half - total_amount / 2).abs() <= 10);
 
-    // After warmup (t=40): should accrue at full 100% rate
-    set_timestamp(&env, 1_040);
-    let claimable_at_40 = client.claimable(&grant_id);
-    // Previous + 10 seconds at 100% = claimable_at_30 + 1000
-    assert!(claimable_at_40 >= claimable_at_30 + 1000);
+    set_timestamp(&env, year_secs);
+    let full = client.claimable(&104);
+    assert!((full - total_amount).abs() <= 10);
 }
 
 #[test]
 fn test_no_warmup_period() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 100;
-    let total_amount: i128 = 10_000; // 100 tokens with 2 decimals = 10000 base units
-    let duration_seconds: u64 = 31_536_000; // 1 year in seconds
-
-    // Calculate scaled flow rate: (amount * SCALING_FACTOR) / duration
-    // This gives us a non-zero rate even for small amounts over long durations
-    let scaled_flow_rate: i128 = (total_amount * SCALING_FACTOR) / (duration_seconds as i128);
-
-    // Verify the scaled rate is non-zero (this would be 0 without scaling)
-    assert!(scaled_flow_rate > 0, "Scaled flow rate should be non-zero");
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &total_amount, &scaled_flow_rate);
-
-    // After 6 months (half the duration), should have ~50% vested
-    let six_months: u64 = duration_seconds / 2;
-    set_timestamp(&env, six_months);
+    let token = Address::generate(&env);
 
-    let claimable_at_6_months = client.claimable(&grant_id);
-    // Allow some tolerance due to integer division
-    let expected_half = total_amount / 2;
-    let tolerance: i128 = 10; // Small tolerance for rounding
-    assert!(
-        (claimable_at_6_months - expected_half).abs() <= tolerance,
-        "At 6 months, claimable {} should be close to {} (tolerance {})",
-        claimable_at_6_months,
-        expected_half,
-        tolerance
+    let grant_id: u64 = 101;
+    set_timestamp(&env, 2_000);
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        100_000,
+        50 * SCALING_FACTOR,
     );
 
-    // After full year, should have 100% vested (capped at total_amount)
-    set_timestamp(&env, duration_seconds);
-    let claimable_at_1_year = client.claimable(&grant_id);
-    assert!(
-        claimable_at_1_year >= total_amount - tolerance && claimable_at_1_year <= total_amount,
-        "At 1 year, claimable {} should be close to total {}",
-        claimable_at_1_year,
-        total_amount
-    );
+    set_timestamp(&env, 2_010);
+    assert_eq!(client.claimable(&grant_id), 500);
+
+    set_timestamp(&env, 2_020);
+    assert_eq!(client.claimable(&grant_id), 1_000);
 }
 
 #[test]
 fn test_slash_inactive_grant_reverts_if_less_than_90_days() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 10;
-    set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000, &10);
+    let keeper = Address::generate(&env);
+    let token = Address::generate(&env);
+
+    let grant_id: u64 = 15;
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        1_000_000,
+        SCALING_FACTOR / 100,
+    );
 
-    // 89 days later (less than 90) – slash should revert
-    const SECS_89_DAYS: u64 = 89 * 24 * 60 * 60;
-    set_timestamp(&env, 1_000 + SECS_89_DAYS);
+    set_timestamp(&env, 89 * 24 * 60 * 60);
     assert_contract_error(
-        client.try_slash_inactive_grant(&grant_id),
+        client.mock_all_auths().try_flag_inactive(&grant_id),
+        Error::GrantNotInactive,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_slash_inactive_grant(&grant_id, &keeper),
         Error::GrantNotInactive,
     );
-
-    let grant = client.get_grant(&grant_id);
-    assert_eq!(grant.status, GrantStatus::Active);
 }
 
 #[test]
 fn test_slash_inactive_grant_reverts_if_not_active() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
+    let keeper = Address::generate(&env);
+    let token = Address::generate(&env);
+
+    let grant_id: u64 = 16;
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        1_000_000,
+        SCALING_FACTOR / 100,
+    );
+    client.mock_all_auths().cancel_grant(&grant_id);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    set_timestamp(&env, 91 * 24 * 60 * 60);
+    assert_contract_error(
+        client.mock_all_auths().try_flag_inactive(&grant_id),
+        Error::InvalidState,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_slash_inactive_grant(&grant_id, &keeper),
+        Error::InvalidState,
+    );
+}
 
-    let grant_id: u64 = 101;
-    let flow_rate: i128 = 50;
+#[test]
+fn test_warmup_with_withdrawal() {
+    let env = Env::default();
+    let (client, admin, _) = setup_contract(&env);
+    let recipient = Address::generate(&env);
+    let token = setup_funded_token(&env, &admin, &client.address, 50_000);
 
-    set_timestamp(&env, 2_000);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &10_000, &flow_rate, &0);
+    let grant_id: u64 = 102;
+    client.mock_all_auths().create_grant(
+        &grant_id,
+        &recipient,
+        &token,
+        &50_000,
+        &(100 * SCALING_FACTOR),
+        &20,
+        &None,
+        &None,
+    );
 
-    // With warmup_duration = 0, should accrue at full rate immediately
-    set_timestamp(&env, 2_010);
-    assert_eq!(client.claimable(&grant_id), 500);
+    // 100 * (0.25 * 10 + 0.75 * 10^2 / 40) = 437.5
+    set_timestamp(&env, 10);
+    assert_eq!(client.claimable(&grant_id), 437);
+    client.mock_all_auths().withdraw(&grant_id, &437);
+    assert_eq!(client.claimable(&grant_id), 0);
 
-    set_timestamp(&env, 2_020);
-    assert_eq!(client.claimable(&grant_id), 1_000);
+    // 1_250 over the full warmup plus 1_000 at the full rate, minus what was withdrawn.
+    set_timestamp(&env, 30);
+    assert_eq!(client.claimable(&grant_id), 2_250 - 437);
 }
 
 #[test]
-fn test_warmup_with_withdrawal() {
-    let grant_id: u64 = 11;
-    set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &1_000, &5);
-    client.mock_all_auths().cancel_grant(&grant_id);
-
-    assert_contract_error(
-        client.try_slash_inactive_grant(&grant_id),
-        Error::InvalidState,
 fn test_low_decimal_token_very_small_amount() {
-    // Scenario: 1 token with 2 decimals (100 base units) over 1 day
-    // Tests precision with very small amounts
-
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
+    let token = Address::generate(&env);
 
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 101;
-    let total_amount: i128 = 100; // 1 token with 2 decimals
-    let duration_seconds: u64 = 86_400; // 1 day in seconds
-
-    let scaled_flow_rate: i128 = (total_amount * SCALING_FACTOR) / (duration_seconds as i128);
-    assert!(scaled_flow_rate > 0, "Scaled flow rate should be non-zero");
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &total_amount, &scaled_flow_rate);
+    // 1.00 unit of a 2-decimal token streamed over one day.
+    let total_amount: i128 = 100;
+    let day_secs: u64 = 24 * 60 * 60;
+    let flow_rate = total_amount * SCALING_FACTOR / i128::from(day_secs);
+    create_simple_grant(&client, 105, &recipient, &token, total_amount, flow_rate);
 
-    // After 12 hours, should have ~50 base units
-    set_timestamp(&env, 43_200);
-    let claimable = client.claimable(&grant_id);
-    assert!(
-        claimable >= 45 && claimable <= 55,
-        "At 12 hours, claimable {} should be around 50",
-        claimable
-    );
+    set_timestamp(&env, day_secs / 2);
+    let half = client.claimable(&105);
+    assert!((45..=55).contains(&half));
 
-    // After full day
-    set_timestamp(&env, 86_400);
-    let claimable_full = client.claimable(&grant_id);
-    assert!(
-        claimable_full >= 95 && claimable_full <= 100,
-        "At 1 day, claimable {} should be close to 100",
-        claimable_full
-    );
+    set_timestamp(&env, day_secs);
+    let full = client.claimable(&105);
+    assert!((95..=100).contains(&full));
 }
 
 #[test]
 fn test_high_precision_long_duration_10_years() {
-    // Scenario: Large grant over 10 years
-    // Tests that precision is maintained over very long durations
-
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
+    let token = Address::generate(&env);
 
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 102;
-    let flow_rate: i128 = 100;
-    let warmup_duration: u64 = 20;
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
-
-    let grant_id: u64 = 102;
-    let total_amount: i128 = 1_000_000_000; // 1 billion base units
-    let duration_seconds: u64 = 315_360_000; // 10 years in seconds
-
-    let scaled_flow_rate: i128 = (total_amount * SCALING_FACTOR) / (duration_seconds as i128);
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &50_000, &flow_rate, &warmup_duration);
+    let total_amount: i128 = 1_000_000_000;
+    let ten_years_secs: u64 = 10 * 365 * 24 * 60 * 60;
+    let flow_rate = total_amount * SCALING_FACTOR / i128::from(ten_years_secs);
+    create_simple_grant(&client, 106, &recipient, &token, total_amount, flow_rate);
 
-    // Accrue during warmup
-    set_timestamp(&env, 10);
-    let claimable_at_10 = client.claimable(&grant_id);
-    assert!(claimable_at_10 > 0);
-
-    // Withdraw during warmup
-    client.mock_all_auths().withdraw(&grant_id, &claimable_at_10);
-    assert_eq!(client.claimable(&grant_id), 0);
-
-    // Continue accruing after warmup
-    set_timestamp(&env, 30);
-    let claimable_at_30 = client.claimable(&grant_id);
-    // 10 seconds at full rate = 1000
-    assert_eq!(claimable_at_30, 1_000);
-        .create_grant(&grant_id, &recipient, &total_amount, &scaled_flow_rate);
-
-    // After 5 years
-    let five_years: u64 = duration_seconds / 2;
-    set_timestamp(&env, five_years);
-    let claimable_5y = client.claimable(&grant_id);
-    let expected_5y = total_amount / 2;
-    let tolerance: i128 = 1000; // Allow small tolerance for large numbers
-    assert!(
-        (claimable_5y - expected_5y).abs() <= tolerance,
-        "At 5 years, claimable {} should be close to {}",
-        claimable_5y,
-        expected_5y
-    );
+    set_timestamp(&env, ten_years_secs / 2);
+    let half = client.claimable(&106);
+    assert!((half - total_amount / 2).abs() <= 1_000);
 
-    // After 10 years
-    set_timestamp(&env, duration_seconds);
-    let claimable_10y = client.claimable(&grant_id);
-    assert!(
-        claimable_10y >= total_amount - tolerance && claimable_10y <= total_amount,
-        "At 10 years, claimable {} should equal total {}",
-        claimable_10y,
-        total_amount
-    );
+    set_timestamp(&env, ten_years_secs);
+    let full = client.claimable(&106);
+    assert!((full - total_amount).abs() <= 1_000);
 }
 
 #[test]
 fn test_slash_inactive_grant_updates_last_claim_time_on_withdraw() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
-    let treasury = Address::generate(&env);
-
-    let contract_id = env.register_contract(None, GrantContract);
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = setup_funded_token(&env, &admin, &client.address, 1_000_000_000);
 
-    let grant_id: u64 = 12;
+    let grant_id: u64 = 17;
     set_timestamp(&env, 1_000);
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &10_000, &10);
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        1_000_000_000,
+        10 * SCALING_FACTOR,
+    );
 
     set_timestamp(&env, 1_100);
     client.mock_all_auths().withdraw(&grant_id, &1_000);
+    assert_eq!(client.get_grant(&grant_id).last_claim_time, 1_100);
 
-    // 89 days after the withdraw (so last_claim_time = 1_100) – still not 90 days inactive
-    const SECS_89_DAYS: u64 = 89 * 24 * 60 * 60;
-    set_timestamp(&env, 1_100 + SECS_89_DAYS);
+    // 90 days after creation, but not 90 days after the withdrawal.
+    set_timestamp(&env, 1_000 + 90 * 24 * 60 * 60);
     assert_contract_error(
-        client.try_slash_inactive_grant(&grant_id),
+        client.mock_all_auths().try_flag_inactive(&grant_id),
         Error::GrantNotInactive,
     );
-fn test_withdraw_converts_to_correct_decimals() {
-    // Verify that withdraw returns amounts in correct token decimals
-    // (not scaled values)
+}
 
+#[test]
+fn test_withdraw_converts_to_correct_decimals() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = setup_funded_token(&env, &admin, &client.address, 1_000);
 
     let grant_id: u64 = 103;
-    let total_amount: i128 = 1_000; // 10 tokens with 2 decimals
-
-    // Simple rate: 10 tokens per second (scaled)
-    let scaled_flow_rate: i128 = 10 * SCALING_FACTOR;
-
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &total_amount, &scaled_flow_rate);
+    create_simple_grant(
+        &client,
+        grant_id,
+        &recipient,
+        &token,
+        1_000,
+        10 * SCALING_FACTOR,
+    );
 
-    // After 50 seconds, should have 500 base units claimable
     set_timestamp(&env, 50);
     assert_eq!(client.claimable(&grant_id), 500);
 
-    // Withdraw 300 base units
     client.mock_all_auths().withdraw(&grant_id, &300);
-
     let grant = client.get_grant(&grant_id);
-    // Withdrawn should be in original token units, not scaled
     assert_eq!(grant.withdrawn, 300);
     assert_eq!(grant.claimable, 200);
 
-    // Withdraw remaining
     client.mock_all_auths().withdraw(&grant_id, &200);
-
-    let grant_after = client.get_grant(&grant_id);
-    assert_eq!(grant_after.withdrawn, 500);
-    assert_eq!(grant_after.claimable, 0);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 500);
+    assert_eq!(TokenClient::new(&env, &token).balance(&recipient), 500);
 }
 
-// ── Issue #30 ── Non-Transferable Grantee Roles ─────────────────────────────
-//
-// Criterion 1: there is no transfer_grant / assign_grantee function exposed
-// to the grantee. This is a compile-time guarantee — the GrantContractClient
-// exposes no such methods. The tests below further confirm that withdraw() is
-// strictly bound to the *current* grant.recipient and that only the Admin can
-// change that address via reassign_grantee().
-//
-// Criterion 2: reassign_grantee() is restricted to the DAO Admin.
+// Issue #30: the admin can hand a grant to a new recipient when the original loses access.
 
 #[test]
 fn test_reassign_grantee_requires_admin_auth() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let attacker = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let new_recipient = Address::generate(&env);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 200;
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &1_000, &(5 * SCALING_FACTOR));
+    create_simple_grant(&client, grant_id, &recipient, &token, 1_000, SCALING_FACTOR);
+
+    assert!(client
+        .try_reassign_grantee(&grant_id, &recipient, &new_recipient)
+        .is_err());
 
-    // Call succeeds under mock_all_auths; verify the recorded authorisation
-    // is the Admin — not the recipient, not the attacker.
     client
         .mock_all_auths()
-        .reassign_grantee(&grant_id, &recipient, &attacker);
-
+        .reassign_grantee(&grant_id, &recipient, &new_recipient);
     let auths = env.auths();
-    // Only one auth should have been required
     assert_eq!(auths.len(), 1);
-    // That auth must belong to the admin
     assert_eq!(auths[0].0, admin);
 }
 
 #[test]
 fn test_reassign_grantee_rejects_wrong_old_address() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, _, _) = setup_contract(&env);
     let recipient = Address::generate(&env);
-    let wrong_old = Address::generate(&env);
+    let impostor = Address::generate(&env);
     let new_recipient = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = Address::generate(&env);
 
     let grant_id: u64 = 201;
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &1_000, &(5 * SCALING_FACTOR));
+    create_simple_grant(&client, grant_id, &recipient, &token, 1_000, SCALING_FACTOR);
 
-    // Passing a `wrong_old` that doesn't match the stored recipient must fail
     assert_contract_error(
         client
             .mock_all_auths()
-            .try_reassign_grantee(&grant_id, &wrong_old, &new_recipient),
+            .try_reassign_grantee(&grant_id, &impostor, &new_recipient),
         Error::GranteeMismatch,
     );
 
@@ -1114,19 +748,20 @@ fn test_reassign_grantee_rejects_wrong_old_address() {
 #[test]
 fn test_reassign_grantee_transfers_withdraw_right_to_new_recipient() {
     let env = Env::default();
-    let admin = Address::generate(&env);
+    let (client, admin, _) = setup_contract(&env);
     let original = Address::generate(&env);
     let new_recipient = Address::generate(&env);
-
-    let contract_id = env.register(GrantContract, ());
-    let client = GrantContractClient::new(&env, &contract_id);
+    let token = setup_funded_token(&env, &admin, &client.address, 1_000);
 
     let grant_id: u64 = 202;
-    set_timestamp(&env, 0);
-    client.mock_all_auths().initialize(&admin);
-    client
-        .mock_all_auths()
-        .create_grant(&grant_id, &original, &1_000, &(10 * SCALING_FACTOR));
+    create_simple_grant(
+        &client,
+        grant_id,
+        &original,
+        &token,
+        1_000,
+        10 * SCALING_FACTOR,
+    );
 
     set_timestamp(&env, 10);
     // 100 tokens have accrued; reassign before any withdrawal
@@ -1134,14 +769,2283 @@ fn test_reassign_grantee_transfers_withdraw_right_to_new_recipient() {
         .mock_all_auths()
         .reassign_grantee(&grant_id, &original, &new_recipient);
 
-    // grant.recipient is now new_recipient
     let grant = client.get_grant(&grant_id);
     assert_eq!(grant.recipient, new_recipient);
 
-    // new_recipient can withdraw the accrued amount
-    // (mock_all_auths satisfies the new recipient.require_auth() inside withdraw)
+    // The new recipient now receives the accrued amount.
     client.mock_all_auths().withdraw(&grant_id, &100);
 
-    let after = client.get_grant(&grant_id);
-    assert_eq!(after.withdrawn, 100);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 100);
+    assert_eq!(TokenClient::new(&env, &token).balance(&new_recipient), 100);
+    assert_eq!(TokenClient::new(&env, &token).balance(&original), 0);
+}
+
+fn create_simple_grant(
+    client: &GrantContractClient,
+    grant_id: u64,
+    recipient: &Address,
+    token: &Address,
+    total_amount: i128,
+    flow_rate: i128,
+) {
+    client.mock_all_auths().create_grant(
+        &grant_id,
+        recipient,
+        token,
+        &total_amount,
+        &flow_rate,
+        &0,
+        &None,
+        &None,
+    );
+}
+
+fn setup_funded_token(env: &Env, admin: &Address, contract_id: &Address, amount: i128) -> Address {
+    let token = env.register_stellar_asset_contract_v2(admin.clone());
+    StellarAssetClient::new(env, &token.address())
+        .mock_all_auths()
+        .mint(contract_id, &amount);
+    token.address()
+}
+
+#[test]
+fn test_withdraw_all_settles_every_grant_in_one_transfer() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 10_000);
+    create_simple_grant(&client, 300, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+    create_simple_grant(&client, 301, &recipient, &grant_token, 1_000, 2 * SCALING_FACTOR);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&301, &5);
+
+    let breakdown = client.mock_all_auths().withdraw_all(&recipient);
+    assert_eq!(breakdown.len(), 2);
+    assert_eq!(
+        breakdown.get(0).unwrap(),
+        GrantWithdrawal {
+            grant_id: 300,
+            token: grant_token.clone(),
+            amount: 50,
+        }
+    );
+    assert_eq!(
+        breakdown.get(1).unwrap(),
+        GrantWithdrawal {
+            grant_id: 301,
+            token: grant_token.clone(),
+            amount: 15,
+        }
+    );
+
+    // 5 from the earlier single-grant withdrawal plus 65 from withdraw_all.
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&recipient), 70);
+    assert_eq!(client.claimable(&300), 0);
+    assert_eq!(client.get_grant(&301).withdrawn, 20);
+}
+
+#[test]
+fn test_withdraw_all_rejects_when_nothing_claimable() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 302, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw_all(&recipient),
+        Error::NothingToWithdraw,
+    );
+}
+
+#[test]
+fn test_recipient_index_follows_reassign_grantee() {
+    let env = Env::default();
+    let original = Address::generate(&env);
+    let new_recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 303, &original, &grant_token, 1_000, 5 * SCALING_FACTOR);
+    assert_eq!(client.get_recipient_grants(&original), vec![&env, 303_u64]);
+
+    client
+        .mock_all_auths()
+        .reassign_grantee(&303, &original, &new_recipient);
+
+    assert_eq!(client.get_recipient_grants(&original).len(), 0);
+    assert_eq!(client.get_recipient_grants(&new_recipient), vec![&env, 303_u64]);
+}
+
+#[test]
+fn test_withdraw_all_pays_each_token_separately() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let usdc = setup_funded_token(&env, &admin, &client.address, 1_000);
+    let aqua = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 310, &recipient, &usdc, 1_000, 3 * SCALING_FACTOR);
+    create_simple_grant(&client, 311, &recipient, &aqua, 1_000, 7 * SCALING_FACTOR);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw_all(&recipient);
+
+    assert_eq!(TokenClient::new(&env, &usdc).balance(&recipient), 30);
+    assert_eq!(TokenClient::new(&env, &aqua).balance(&recipient), 70);
+}
+
+#[test]
+fn test_rescue_tokens_protects_allocation_of_rescued_token_only() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let to = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let usdc = setup_funded_token(&env, &admin, &client.address, 1_500);
+    let aqua = setup_funded_token(&env, &admin, &client.address, 200);
+    create_simple_grant(&client, 312, &recipient, &usdc, 1_000, SCALING_FACTOR);
+
+    // Only 500 USDC is unallocated.
+    assert_contract_error(
+        client.mock_all_auths().try_rescue_tokens(&usdc, &501, &to),
+        Error::RescueWouldViolateAllocated,
+    );
+    client.mock_all_auths().rescue_tokens(&usdc, &500, &to);
+
+    // No grant is paid in AQUA, so all of it is rescuable.
+    client.mock_all_auths().rescue_tokens(&aqua, &200, &to);
+    assert_eq!(TokenClient::new(&env, &aqua).balance(&to), 200);
+}
+
+/// Minimal token that burns a 1% fee on every transfer, for fee-on-transfer accounting tests.
+mod fee_token {
+    use soroban_sdk::{contract, contractimpl, Address, Env};
+
+    #[contract]
+    pub struct FeeToken;
+
+    #[contractimpl]
+    impl FeeToken {
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().persistent().set(&to, &(balance + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().persistent().get(&id).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+            let fee = amount / 100;
+            let from_balance = Self::balance(env.clone(), from.clone());
+            env.storage()
+                .persistent()
+                .set(&from, &(from_balance - amount));
+            let to_balance = Self::balance(env.clone(), to.clone());
+            env.storage()
+                .persistent()
+                .set(&to, &(to_balance + amount - fee));
+        }
+    }
+}
+
+use fee_token::{FeeToken, FeeTokenClient};
+
+fn setup_fee_token_grant(
+    env: &Env,
+    fee_policy: FeePolicy,
+    reserve: i128,
+) -> (GrantContractClient<'_>, FeeTokenClient<'_>, Address) {
+    let (client, admin, _) = setup_contract(env);
+    let recipient = Address::generate(env);
+    let token = FeeTokenClient::new(env, &env.register(FeeToken, ()));
+    token.mint(&admin, &1_000);
+    token.mint(&client.address, &reserve);
+
+    client.mock_all_auths().set_fee_policy(&fee_policy);
+    create_simple_grant(&client, 320, &recipient, &token.address, 1_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().fund_grant(&320, &admin, &1_000);
+
+    (client, token, recipient)
+}
+
+#[test]
+fn test_fund_grant_credits_amount_actually_received() {
+    let env = Env::default();
+    let (client, token, _) = setup_fee_token_grant(&env, FeePolicy::Recipient, 0);
+
+    let grant = client.get_grant(&320);
+    assert_eq!(grant.funded, 990);
+    // The fee comes out of the grant, not out of other grants' deposits.
+    assert_eq!(grant.total_amount, 990);
+    assert_eq!(token.balance(&client.address), 990);
+
+    set_timestamp(&env, 1_000);
+    assert_eq!(client.claimable(&320), 990);
+}
+
+#[test]
+fn test_fee_absorption_does_not_spend_directly_funded_grants() {
+    let env = Env::default();
+    let (client, token, recipient) = setup_fee_token_grant(&env, FeePolicy::Contract, 0);
+    // Grant 321 is funded by a direct transfer rather than through fund_grant.
+    let other = Address::generate(&env);
+    create_simple_grant(&client, 321, &other, &token.address, 5_000, SCALING_FACTOR);
+    token.mint(&client.address, &5_000);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&320, &100);
+
+    // With no unallocated reserve, the recipient bears the fee.
+    assert_eq!(token.balance(&recipient), 99);
+    let grant = client.get_grant(&320);
+    assert_eq!(grant.withdrawn, 100);
+    assert_eq!(token.balance(&client.address), 890 + 5_000);
+}
+
+#[test]
+fn test_withdraw_fee_absorbed_by_recipient() {
+    let env = Env::default();
+    let (client, token, recipient) = setup_fee_token_grant(&env, FeePolicy::Recipient, 0);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&320, &100);
+
+    assert_eq!(token.balance(&recipient), 99);
+    let grant = client.get_grant(&320);
+    assert_eq!(grant.withdrawn, 100);
+    assert_eq!(grant.claimable, 0);
+}
+
+#[test]
+fn test_withdraw_fee_absorbed_by_contract_stays_solvent() {
+    let env = Env::default();
+    let (client, token, recipient) = setup_fee_token_grant(&env, FeePolicy::Contract, 100);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&320, &100);
+
+    assert_eq!(token.balance(&recipient), 99);
+    let grant = client.get_grant(&320);
+    // Only what arrived counts as withdrawn; the fee stays claimable.
+    assert_eq!(grant.withdrawn, 99);
+    assert_eq!(grant.claimable, 1);
+    assert!(token.balance(&client.address) >= grant.total_amount - grant.withdrawn);
+}
+
+#[test]
+fn test_withdraw_fee_falls_back_to_recipient_without_reserve() {
+    let env = Env::default();
+    let (client, token, recipient) = setup_fee_token_grant(&env, FeePolicy::Contract, 0);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&320, &100);
+
+    assert_eq!(token.balance(&recipient), 99);
+    let grant = client.get_grant(&320);
+    assert_eq!(grant.withdrawn, 100);
+    assert_eq!(grant.claimable, 0);
+
+    // withdraw_all falls back the same way.
+    set_timestamp(&env, 40);
+    client.mock_all_auths().withdraw_all(&recipient);
+    assert_eq!(token.balance(&recipient), 99 + 99);
+    assert_eq!(client.get_grant(&320).withdrawn, 200);
+}
+
+#[test]
+fn test_sync_rebase_sends_surplus_to_treasury_and_reports_shortfall() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, treasury) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 322, &recipient, &grant_token, 1_000, SCALING_FACTOR);
+    assert!(client.try_sync_rebase(&grant_token).is_err());
+
+    env.mock_all_auths();
+    assert_eq!(client.sync_rebase(&grant_token), 0);
+    assert_eq!(env.auths()[0].0, admin);
+
+    // A positive rebase grows the contract's balance without a transfer.
+    StellarAssetClient::new(&env, &grant_token)
+        .mock_all_auths()
+        .mint(&client.address, &50);
+    assert_eq!(client.sync_rebase(&grant_token), 50);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&treasury), 50);
+
+    create_simple_grant(&client, 323, &recipient, &grant_token, 200, SCALING_FACTOR);
+    assert_eq!(client.sync_rebase(&grant_token), -200);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&treasury), 50);
+}
+
+#[test]
+fn test_create_grant_with_rate_derives_scaled_flow_rate_from_decimals() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    // Stellar asset contracts use 7 decimals.
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 0);
+    let unit: i128 = 10_000_000;
+    client.mock_all_auths().create_grant_with_rate(
+        &330,
+        &recipient,
+        &grant_token,
+        &(10_000 * unit),
+        &864,
+        &0,
+        &RatePeriod::Day,
+        &0,
+        &None,
+    );
+
+    // 864 tokens per day is 0.01 token per second.
+    let grant = client.get_grant(&330);
+    assert_eq!(grant.flow_rate, unit / 100 * SCALING_FACTOR);
+
+    set_timestamp(&env, 86_400);
+    assert_eq!(client.claimable(&330), 864 * unit);
+    assert_eq!(client.rate_per_period(&330, &RatePeriod::Second, &2), 1);
+    assert_eq!(client.rate_per_period(&330, &RatePeriod::Day, &0), 864);
+    assert_eq!(client.rate_per_period(&330, &RatePeriod::Week, &0), 864 * 7);
+}
+
+#[test]
+fn test_rate_per_period_rounds_monthly_rate_back_exactly() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 0);
+    client.mock_all_auths().create_grant_with_rate(
+        &331,
+        &recipient,
+        &grant_token,
+        &1_000_000_000_000,
+        &1_000,
+        &0,
+        &RatePeriod::Month,
+        &0,
+        &None,
+    );
+
+    // The per-second rate is truncated, but converting back recovers the input.
+    assert_eq!(client.rate_per_period(&331, &RatePeriod::Month, &0), 1_000);
+
+    // 123.4567 tokens per month survives the round trip at the same scale.
+    client.mock_all_auths().create_grant_with_rate(
+        &333,
+        &recipient,
+        &grant_token,
+        &1_000_000_000_000,
+        &1_234_567,
+        &4,
+        &RatePeriod::Month,
+        &0,
+        &None,
+    );
+    assert_eq!(client.rate_per_period(&333, &RatePeriod::Month, &4), 1_234_567);
+    assert_eq!(client.rate_per_period(&333, &RatePeriod::Month, &0), 123);
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_with_rate(
+            &332,
+            &recipient,
+            &grant_token,
+            &1_000,
+            &0,
+            &0,
+            &RatePeriod::Month,
+            &0,
+            &None,
+        ),
+        Error::InvalidRate,
+    );
+}
+
+#[test]
+fn test_settle_frequency_does_not_change_total_accrual() {
+    let cases: [(i128, u64); 4] = [
+        (SCALING_FACTOR / 3, 1),
+        (SCALING_FACTOR * 7 / 10, 3),
+        (123_457, 17),
+        (2 * SCALING_FACTOR + 1, 101),
+    ];
+
+    for (rate, step) in cases {
+        let env = Env::default();
+        let recipient = Address::generate(&env);
+        let grant_token = Address::generate(&env);
+
+        let (client, _, _) = setup_contract(&env);
+        create_simple_grant(&client, 340, &recipient, &grant_token, 1_000_000, rate);
+        create_simple_grant(&client, 341, &recipient, &grant_token, 1_000_000, rate);
+
+        // Grant 340 is settled every `step` seconds, grant 341 only once at the end.
+        let steps: u64 = 50;
+        for i in 1..=steps {
+            set_timestamp(&env, i * step);
+            client.mock_all_auths().propose_rate_change(&340, &rate, &None);
+        }
+
+        let elapsed = i128::from(steps * step);
+        let expected = rate * elapsed / SCALING_FACTOR;
+        assert_eq!(client.claimable(&341), expected);
+        assert_eq!(client.claimable(&340), expected);
+    }
+}
+
+fn setup_warmup_grant(
+    env: &Env,
+    grant_id: u64,
+    rate: i128,
+    warmup_duration: u64,
+    warmup_curve: Option<WarmupCurve>,
+) -> GrantContractClient<'_> {
+    let (client, _, _) = setup_contract(env);
+    let recipient = Address::generate(env);
+    let grant_token = Address::generate(env);
+
+    client.mock_all_auths().create_grant(
+        &grant_id,
+        &recipient,
+        &grant_token,
+        &1_000_000,
+        &rate,
+        &warmup_duration,
+        &warmup_curve,
+        &None,
+    );
+    client
+}
+
+#[test]
+fn test_default_warmup_is_integrated_over_the_interval() {
+    let env = Env::default();
+    let client = setup_warmup_grant(&env, 350, 100 * SCALING_FACTOR, 30, None);
+
+    // 25% -> 100% linearly over 30s averages 62.5%, whatever the settlement pattern.
+    set_timestamp(&env, 10);
+    assert_eq!(client.claimable(&350), 375);
+    client.mock_all_auths().propose_rate_change(&350, &(100 * SCALING_FACTOR), &None);
+
+    set_timestamp(&env, 30);
+    assert_eq!(client.claimable(&350), 1_875);
+
+    set_timestamp(&env, 40);
+    assert_eq!(client.claimable(&350), 2_875);
+}
+
+#[test]
+fn test_step_warmup_holds_start_rate_until_warmup_ends() {
+    let env = Env::default();
+    let curve = WarmupCurve {
+        start_bps: 5_000,
+        shape: WarmupShape::Step,
+    };
+    let client = setup_warmup_grant(&env, 351, 10 * SCALING_FACTOR, 100, Some(curve));
+
+    set_timestamp(&env, 100);
+    assert_eq!(client.claimable(&351), 500);
+
+    set_timestamp(&env, 110);
+    assert_eq!(client.claimable(&351), 600);
+}
+
+#[test]
+fn test_quadratic_warmup_eases_in() {
+    let env = Env::default();
+    let curve = WarmupCurve {
+        start_bps: 0,
+        shape: WarmupShape::Quadratic,
+    };
+    let client = setup_warmup_grant(&env, 352, 90 * SCALING_FACTOR, 30, Some(curve));
+
+    // Integral of 90 * (t / 30)^2 from 0 to 15 is 112.5.
+    set_timestamp(&env, 15);
+    assert_eq!(client.claimable(&352), 112);
+
+    // A third of the full rate over the whole warmup.
+    set_timestamp(&env, 30);
+    assert_eq!(client.claimable(&352), 900);
+}
+
+#[test]
+fn test_create_grant_rejects_warmup_start_above_full_rate() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    let curve = WarmupCurve {
+        start_bps: 10_001,
+        shape: WarmupShape::Linear,
+    };
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant(
+            &353,
+            &recipient,
+            &grant_token,
+            &1_000,
+            &SCALING_FACTOR,
+            &30,
+            &Some(curve),
+            &None,
+        ),
+        Error::InvalidWarmup,
+    );
+}
+
+#[test]
+fn test_rate_schedule_accrues_across_segment_boundaries() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 10_000);
+    let schedule = vec![
+        &env,
+        RateSegment {
+            start: 100,
+            rate: 4 * SCALING_FACTOR,
+        },
+        RateSegment {
+            start: 200,
+            rate: SCALING_FACTOR,
+        },
+    ];
+    client.mock_all_auths().create_grant(
+        &360,
+        &recipient,
+        &grant_token,
+        &10_000,
+        &(10 * SCALING_FACTOR),
+        &0,
+        &None,
+        &Some(schedule),
+    );
+    assert_eq!(client.upcoming_rate_segments(&360).len(), 2);
+
+    set_timestamp(&env, 150);
+    assert_eq!(client.claimable(&360), 1_000 + 50 * 4);
+    client.mock_all_auths().withdraw(&360, &100);
+
+    let upcoming = client.upcoming_rate_segments(&360);
+    assert_eq!(upcoming.len(), 1);
+    assert_eq!(upcoming.get(0).unwrap().start, 200);
+    assert_eq!(client.get_grant(&360).flow_rate, 4 * SCALING_FACTOR);
+
+    set_timestamp(&env, 250);
+    assert_eq!(client.claimable(&360), 1_000 + 100 * 4 + 50 - 100);
+    assert_eq!(client.upcoming_rate_segments(&360).len(), 0);
+}
+
+#[test]
+fn test_create_grant_rejects_unordered_rate_schedule() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    set_timestamp(&env, 1_000);
+
+    let unordered = vec![
+        &env,
+        RateSegment {
+            start: 2_000,
+            rate: SCALING_FACTOR,
+        },
+        RateSegment {
+            start: 1_500,
+            rate: SCALING_FACTOR,
+        },
+    ];
+    let in_the_past = vec![
+        &env,
+        RateSegment {
+            start: 1_000,
+            rate: SCALING_FACTOR,
+        },
+    ];
+
+    for schedule in [unordered, in_the_past] {
+        assert_contract_error(
+            client.mock_all_auths().try_create_grant(
+                &361,
+                &recipient,
+                &grant_token,
+                &10_000,
+                &SCALING_FACTOR,
+                &0,
+                &None,
+                &Some(schedule),
+            ),
+            Error::InvalidSchedule,
+        );
+    }
+}
+
+#[test]
+fn test_cancel_pending_rate_keeps_current_rate() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 370, &recipient, &grant_token, 1_000_000, 2 * SCALING_FACTOR);
+
+    let justification = BytesN::from_array(&env, &[7; 32]);
+    set_timestamp(&env, 100);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&370, &(5 * SCALING_FACTOR), &Some(justification.clone()));
+
+    set_timestamp(&env, 1_000);
+    let pending = client.pending_rate_change(&370).unwrap();
+    assert_eq!(
+        pending,
+        PendingRateChange {
+            current_rate: 2 * SCALING_FACTOR,
+            pending_rate: 5 * SCALING_FACTOR,
+            effective_timestamp: 100 + RATE_INCREASE_TIMELOCK_SECS,
+            seconds_remaining: 100 + RATE_INCREASE_TIMELOCK_SECS - 1_000,
+            justification: Some(justification),
+        }
+    );
+
+    client.mock_all_auths().cancel_pending_rate(&370);
+    assert_eq!(client.pending_rate_change(&370), None);
+
+    // The increase never applies.
+    set_timestamp(&env, 200 + RATE_INCREASE_TIMELOCK_SECS);
+    let grant = client.get_grant(&370);
+    assert_eq!(grant.flow_rate, 2 * SCALING_FACTOR);
+    assert_eq!(
+        grant.claimable,
+        2 * i128::from(200 + RATE_INCREASE_TIMELOCK_SECS)
+    );
+}
+
+#[test]
+fn test_cancel_pending_rate_rejects_when_nothing_pending() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 371, &recipient, &grant_token, 1_000_000, 2 * SCALING_FACTOR);
+
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_pending_rate(&371),
+        Error::NoPendingRateChange,
+    );
+
+    // Once the timelock has elapsed the increase is applied, not pending.
+    client
+        .mock_all_auths()
+        .propose_rate_change(&371, &(3 * SCALING_FACTOR), &None);
+    set_timestamp(&env, RATE_INCREASE_TIMELOCK_SECS);
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_pending_rate(&371),
+        Error::NoPendingRateChange,
+    );
+}
+
+const RATE_DECREASE_NOTICE_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[test]
+fn test_rate_decrease_waits_for_notice_period() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    client
+        .mock_all_auths()
+        .set_rate_decrease_notice(&RATE_DECREASE_NOTICE_SECS);
+    create_simple_grant(&client, 380, &recipient, &grant_token, 100_000_000, 10 * SCALING_FACTOR);
+
+    set_timestamp(&env, 100);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&380, &(4 * SCALING_FACTOR), &None);
+
+    let grant = client.get_grant(&380);
+    assert_eq!(grant.flow_rate, 10 * SCALING_FACTOR);
+    assert_eq!(grant.pending_rate, 4 * SCALING_FACTOR);
+    assert_eq!(grant.effective_timestamp, 100 + RATE_DECREASE_NOTICE_SECS);
+
+    let effective = 100 + RATE_DECREASE_NOTICE_SECS;
+    set_timestamp(&env, effective + 10);
+    assert_eq!(
+        client.claimable(&380),
+        10 * i128::from(effective) + 4 * 10
+    );
+}
+
+#[test]
+fn test_grantee_can_accept_rate_decrease_early() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    client
+        .mock_all_auths()
+        .set_rate_decrease_notice(&RATE_DECREASE_NOTICE_SECS);
+    create_simple_grant(&client, 381, &recipient, &grant_token, 100_000_000, 10 * SCALING_FACTOR);
+
+    set_timestamp(&env, 100);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&381, &(4 * SCALING_FACTOR), &None);
+
+    set_timestamp(&env, 200);
+    client.mock_all_auths().accept_rate_decrease(&381);
+
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, recipient);
+
+    let grant = client.get_grant(&381);
+    assert_eq!(grant.flow_rate, 4 * SCALING_FACTOR);
+    assert_eq!(grant.effective_timestamp, 0);
+
+    set_timestamp(&env, 300);
+    assert_eq!(client.claimable(&381), 2_000 + 400);
+
+    // Increases can't be "accepted" early.
+    client
+        .mock_all_auths()
+        .propose_rate_change(&381, &(8 * SCALING_FACTOR), &None);
+    assert_contract_error(
+        client.mock_all_auths().try_accept_rate_decrease(&381),
+        Error::NoPendingRateChange,
+    );
+}
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+#[test]
+fn test_slash_uses_per_grant_inactivity_threshold() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, admin, treasury) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 10_000);
+    create_simple_grant(&client, 390, &recipient, &grant_token, 10_000, 0);
+    client
+        .mock_all_auths()
+        .set_slashing_policy(&390, &(30 * DAY_SECS), &10_000);
+
+    set_timestamp(&env, 30 * DAY_SECS - 1);
+    assert_contract_error(client.try_flag_inactive(&390), Error::GrantNotInactive);
+
+    set_timestamp(&env, 30 * DAY_SECS);
+    client.flag_inactive(&390);
+    set_timestamp(&env, 37 * DAY_SECS);
+    client.mock_all_auths().slash_inactive_grant(&390, &keeper);
+    assert_eq!(client.get_grant(&390).status, GrantStatus::Cancelled);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&treasury), 10_000);
+}
+
+#[test]
+fn test_partial_slash_keeps_grant_active() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, admin, treasury) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 10_000);
+    client
+        .mock_all_auths()
+        .set_default_inactivity_threshold(&(10 * DAY_SECS));
+    create_simple_grant(&client, 391, &recipient, &grant_token, 10_000, 0);
+    client.mock_all_auths().set_slashing_policy(&391, &0, &2_500);
+    client.mock_all_auths().set_slash_grace_period(&0);
+
+    set_timestamp(&env, 10 * DAY_SECS);
+    client.flag_inactive(&391);
+    client.mock_all_auths().slash_inactive_grant(&391, &keeper);
+
+    let grant = client.get_grant(&391);
+    assert_eq!(grant.status, GrantStatus::Active);
+    assert_eq!(grant.total_amount, 7_500);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&treasury), 2_500);
+
+    // The inactivity clock restarts after a partial slash.
+    assert_contract_error(
+        client.mock_all_auths().try_slash_inactive_grant(&391, &keeper),
+        Error::GrantNotInactive,
+    );
+}
+
+#[test]
+fn test_heartbeat_resets_inactivity_without_withdrawing() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 392, &recipient, &grant_token, 1_000_000_000, SCALING_FACTOR);
+
+    set_timestamp(&env, 80 * DAY_SECS);
+    client.mock_all_auths().heartbeat(&392);
+
+    let grant = client.get_grant(&392);
+    assert_eq!(grant.last_claim_time, 80 * DAY_SECS);
+    assert_eq!(grant.withdrawn, 0);
+    assert_eq!(grant.claimable, i128::from(80 * DAY_SECS));
+
+    set_timestamp(&env, 100 * DAY_SECS);
+    assert_contract_error(
+        client.mock_all_auths().try_slash_inactive_grant(&392, &keeper),
+        Error::GrantNotInactive,
+    );
+}
+
+#[test]
+fn test_slash_requires_flag_and_elapsed_grace_window() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 10_000);
+    create_simple_grant(&client, 400, &recipient, &grant_token, 10_000, 0);
+
+    set_timestamp(&env, 90 * DAY_SECS);
+    assert_contract_error(
+        client.mock_all_auths().try_slash_inactive_grant(&400, &keeper),
+        Error::NotFlaggedInactive,
+    );
+
+    assert_eq!(client.flag_inactive(&400), 97 * DAY_SECS);
+    assert_contract_error(client.try_flag_inactive(&400), Error::AlreadyFlaggedInactive);
+
+    set_timestamp(&env, 97 * DAY_SECS - 1);
+    assert_contract_error(
+        client.mock_all_auths().try_slash_inactive_grant(&400, &keeper),
+        Error::GracePeriodActive,
+    );
+
+    set_timestamp(&env, 97 * DAY_SECS);
+    client.mock_all_auths().slash_inactive_grant(&400, &keeper);
+    assert_eq!(client.get_grant(&400).status, GrantStatus::Cancelled);
+}
+
+#[test]
+fn test_grantee_activity_during_grace_window_clears_flag() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 401, &recipient, &grant_token, 10_000, 0);
+
+    // The grantee missed the deadline by a day but responds within the grace window.
+    set_timestamp(&env, 91 * DAY_SECS);
+    client.flag_inactive(&401);
+    set_timestamp(&env, 92 * DAY_SECS);
+    client.mock_all_auths().heartbeat(&401);
+    assert_eq!(client.get_grant(&401).inactive_flagged_at, 0);
+
+    set_timestamp(&env, 100 * DAY_SECS);
+    assert_contract_error(
+        client.mock_all_auths().try_slash_inactive_grant(&401, &keeper),
+        Error::GrantNotInactive,
+    );
+    assert_eq!(client.get_grant(&401).status, GrantStatus::Active);
+}
+
+fn setup_slashable_grant(
+    env: &Env,
+    grant_id: u64,
+    total_amount: i128,
+) -> (GrantContractClient<'_>, Address, Address) {
+    let (client, admin, treasury) = setup_contract(env);
+    let recipient = Address::generate(env);
+    let grant_token = setup_funded_token(env, &admin, &client.address, total_amount);
+
+    client.mock_all_auths().set_slash_grace_period(&0);
+    create_simple_grant(&client, grant_id, &recipient, &grant_token, total_amount, 0);
+
+    set_timestamp(env, 90 * DAY_SECS);
+    client.flag_inactive(&grant_id);
+
+    (client, grant_token, treasury)
+}
+
+#[test]
+fn test_slash_pays_capped_keeper_bounty_before_treasury() {
+    let env = Env::default();
+    let keeper = Address::generate(&env);
+    let (client, grant_token, treasury) = setup_slashable_grant(&env, 410, 10_000);
+    let token = TokenClient::new(&env, &grant_token);
+
+    // 5% of 10_000 is 500, capped at 300.
+    client
+        .mock_all_auths()
+        .set_keeper_bounty(&grant_token, &500, &300);
+    assert_eq!(
+        client.mock_all_auths().slash_inactive_grant(&410, &keeper),
+        300
+    );
+
+    assert_eq!(token.balance(&keeper), 300);
+    assert_eq!(token.balance(&treasury), 9_700);
+}
+
+#[test]
+fn test_keeper_bounty_never_exceeds_slashed_balance() {
+    let env = Env::default();
+    let keeper = Address::generate(&env);
+    let (client, grant_token, treasury) = setup_slashable_grant(&env, 411, 1_000);
+    let token = TokenClient::new(&env, &grant_token);
+
+    client
+        .mock_all_auths()
+        .set_keeper_bounty(&grant_token, &10_000, &i128::MAX);
+    assert_eq!(
+        client.mock_all_auths().slash_inactive_grant(&411, &keeper),
+        1_000
+    );
+
+    assert_eq!(token.balance(&keeper), 1_000);
+    assert_eq!(token.balance(&treasury), 0);
+    assert_eq!(token.balance(&client.address), 0);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_keeper_bounty(&grant_token, &10_001, &0),
+        Error::InvalidAmount,
+    );
+}
+
+#[test]
+fn test_slash_without_keeper_bounty_sends_everything_to_treasury() {
+    let env = Env::default();
+    let keeper = Address::generate(&env);
+    let (client, grant_token, treasury) = setup_slashable_grant(&env, 412, 2_000);
+    let token = TokenClient::new(&env, &grant_token);
+
+    assert_eq!(
+        client.mock_all_auths().slash_inactive_grant(&412, &keeper),
+        0
+    );
+    assert_eq!(token.balance(&keeper), 0);
+    assert_eq!(token.balance(&treasury), 2_000);
+}
+
+fn setup_disputable_grant(
+    env: &Env,
+    grant_id: u64,
+) -> (GrantContractClient<'_>, Address, Address, Address, Address) {
+    let (client, admin, treasury) = setup_contract(env);
+    let recipient = Address::generate(env);
+    let arbitrator = Address::generate(env);
+    let grant_token = setup_funded_token(env, &admin, &client.address, 1_000);
+
+    client
+        .mock_all_auths()
+        .set_arbitrators(&vec![env, arbitrator.clone()]);
+    create_simple_grant(&client, grant_id, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    (client, grant_token, recipient, treasury, arbitrator)
+}
+
+#[test]
+fn test_dispute_freezes_withdrawals_and_rate_changes_until_resumed() {
+    let env = Env::default();
+    let (client, grant_token, recipient, _, arbitrator) = setup_disputable_grant(&env, 420);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().open_dispute(&420, &recipient, &None);
+    let dispute = client.get_grant(&420).dispute;
+    assert_eq!(dispute.opened_by, Some(recipient.clone()));
+    assert_eq!(dispute.opened_at, 10);
+
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&420, &10),
+        Error::UnderDispute,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_propose_rate_change(&420, &SCALING_FACTOR, &None),
+        Error::UnderDispute,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_open_dispute(&420, &recipient, &None),
+        Error::UnderDispute,
+    );
+
+    // Accrual continues while frozen.
+    set_timestamp(&env, 20);
+    assert_eq!(client.claimable(&420), 100);
+
+    client
+        .mock_all_auths()
+        .resolve_dispute(&420, &arbitrator, &DisputeResolution::Resume);
+    let grant = client.get_grant(&420);
+    assert_eq!(grant.dispute, Dispute::default());
+    // Resuming isn't grantee activity; only the 10s spent frozen are excluded.
+    assert_eq!(grant.last_claim_time, 10);
+
+    client.mock_all_auths().withdraw(&420, &100);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&recipient), 100);
+}
+
+#[test]
+fn test_admin_can_cancel_grantee_dispute_but_not_its_own() {
+    let env = Env::default();
+    let (client, admin, _) = setup_contract(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 2_000);
+    create_simple_grant(&client, 423, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().open_dispute(&423, &recipient, &None);
+    client.mock_all_auths().cancel_grant(&423);
+    let grant = client.get_grant(&423);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(grant.dispute, Dispute::default());
+    assert_eq!(grant.claimable, 50);
+
+    create_simple_grant(&client, 424, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().open_dispute(&424, &admin, &None);
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_grant(&424),
+        Error::UnderDispute,
+    );
+}
+
+#[test]
+fn test_admin_resolves_disputes_while_no_arbitrators_are_set() {
+    let env = Env::default();
+    let (client, admin, _) = setup_contract(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 425, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    client.mock_all_auths().open_dispute(&425, &admin, &None);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_resolve_dispute(&425, &recipient, &DisputeResolution::Resume),
+        Error::NotAuthorized,
+    );
+    client
+        .mock_all_auths()
+        .resolve_dispute(&425, &admin, &DisputeResolution::Resume);
+    assert_eq!(client.get_grant(&425).dispute, Dispute::default());
+}
+
+#[test]
+fn test_dispute_split_pays_grantee_share_and_treasury_rest() {
+    let env = Env::default();
+    let (client, grant_token, recipient, treasury, arbitrator) =
+        setup_disputable_grant(&env, 421);
+    let token = TokenClient::new(&env, &grant_token);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&421, &100);
+    client.mock_all_auths().open_dispute(&421, &recipient, &None);
+
+    // 900 unwithdrawn: 30% to the grantee, the rest to the treasury.
+    set_timestamp(&env, 40);
+    client
+        .mock_all_auths()
+        .resolve_dispute(&421, &arbitrator, &DisputeResolution::Split(3_000));
+
+    assert_eq!(token.balance(&recipient), 370);
+    assert_eq!(token.balance(&treasury), 630);
+    assert_eq!(token.balance(&client.address), 0);
+
+    let grant = client.get_grant(&421);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(grant.withdrawn, 370);
+    assert_eq!(grant.claimable, 0);
+    assert_eq!(grant.flow_rate, 0);
+}
+
+#[test]
+fn test_dispute_requires_party_and_arbitrator() {
+    let env = Env::default();
+    let (client, _, _, _, arbitrator) = setup_disputable_grant(&env, 422);
+    let stranger = Address::generate(&env);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_resolve_dispute(&422, &arbitrator, &DisputeResolution::Cancel),
+        Error::NotUnderDispute,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_open_dispute(&422, &stranger, &None),
+        Error::NotAuthorized,
+    );
+
+    let recipient = client.get_grant(&422).recipient;
+    client.mock_all_auths().open_dispute(&422, &recipient, &None);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_resolve_dispute(&422, &stranger, &DisputeResolution::Cancel),
+        Error::NotAuthorized,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_resolve_dispute(&422, &arbitrator, &DisputeResolution::Split(10_001)),
+        Error::InvalidAmount,
+    );
+
+    client
+        .mock_all_auths()
+        .resolve_dispute(&422, &arbitrator, &DisputeResolution::Cancel);
+    assert_eq!(client.get_grant(&422).status, GrantStatus::Cancelled);
+}
+
+const CLAWBACK_TIMELOCK_SECS: u64 = 48 * 60 * 60;
+
+#[test]
+fn test_clawback_holds_funds_then_sends_them_to_treasury_after_timelock() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, treasury) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    let token = TokenClient::new(&env, &grant_token);
+    create_simple_grant(&client, 430, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 20);
+    assert_contract_error(
+        client.mock_all_auths().try_clawback(&430, &101, &7),
+        Error::InvalidAmount,
+    );
+    let executable_at = client.mock_all_auths().clawback(&430, &60, &7);
+    assert_eq!(executable_at, 20 + CLAWBACK_TIMELOCK_SECS);
+    assert_contract_error(
+        client.mock_all_auths().try_clawback(&430, &10, &7),
+        Error::ClawbackPending,
+    );
+
+    // The held amount can't be withdrawn while the clawback is pending.
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&430, &41),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().withdraw(&430, &40);
+
+    set_timestamp(&env, executable_at - 1);
+    assert_contract_error(
+        client.mock_all_auths().try_execute_clawback(&430),
+        Error::ClawbackTimelockActive,
+    );
+
+    set_timestamp(&env, executable_at);
+    assert_eq!(client.mock_all_auths().execute_clawback(&430), 60);
+    assert_eq!(token.balance(&treasury), 60);
+    assert_eq!(token.balance(&recipient), 40);
+
+    let grant = client.get_grant(&430);
+    assert_eq!(grant.pending_clawback, PendingClawback::default());
+    assert_eq!(grant.total_amount, 940);
+    assert_eq!(grant.withdrawn + grant.claimable, 940);
+}
+
+#[test]
+fn test_cancel_clawback_releases_held_funds() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 431, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().clawback(&431, &100, &3);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&431, &1),
+        Error::InvalidAmount,
+    );
+
+    client.mock_all_auths().cancel_clawback(&431);
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_clawback(&431),
+        Error::NoPendingClawback,
+    );
+    client.mock_all_auths().withdraw(&431, &100);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&recipient), 100);
+}
+
+#[test]
+fn test_grantee_withdraws_accrued_balance_after_cancellation() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let (client, admin, treasury) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    let token = TokenClient::new(&env, &grant_token);
+    create_simple_grant(&client, 440, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 30);
+    client.mock_all_auths().withdraw(&440, &50);
+    client.mock_all_auths().cancel_grant(&440);
+
+    // Nothing accrues after cancellation; the 100 settled at cancellation stays claimable.
+    set_timestamp(&env, 100);
+    assert_eq!(client.claimable(&440), 100);
+    assert_eq!(client.finalize_cancelled(&440), 850);
+    assert_eq!(token.balance(&treasury), 850);
+    assert_contract_error(client.try_finalize_cancelled(&440), Error::NothingToFinalize);
+
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&440, &101),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().withdraw(&440, &100);
+    assert_eq!(token.balance(&recipient), 150);
+    assert_eq!(token.balance(&client.address), 0);
+
+    let grant = client.get_grant(&440);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(grant.withdrawn, grant.total_amount);
+}
+
+#[test]
+fn test_finalize_cancelled_rejects_active_grant() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 441, &recipient, &grant_token, 1_000, SCALING_FACTOR);
+
+    assert_contract_error(client.try_finalize_cancelled(&441), Error::InvalidState);
+}
+
+#[test]
+fn test_split_grant_pays_each_recipient_their_share() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let teammate = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    let token = TokenClient::new(&env, &grant_token);
+    create_simple_grant(&client, 450, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    // The 100 accrued before the split stays with the original recipient.
+    set_timestamp(&env, 20);
+    client.mock_all_auths().set_recipient_shares(
+        &450,
+        &vec![
+            &env,
+            RecipientShare {
+                recipient: recipient.clone(),
+                share_bps: 6_000,
+            },
+            RecipientShare {
+                recipient: teammate.clone(),
+                share_bps: 4_000,
+            },
+        ],
+    );
+
+    set_timestamp(&env, 40);
+    assert_eq!(client.share_claimable(&450, &recipient), 160);
+    assert_eq!(client.share_claimable(&450, &teammate), 40);
+
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&450, &10),
+        Error::SplitGrant,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw_share(&450, &teammate, &41),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().withdraw_share(&450, &teammate, &40);
+    client.mock_all_auths().withdraw_share(&450, &recipient, &160);
+    assert_eq!(token.balance(&teammate), 40);
+    assert_eq!(token.balance(&recipient), 160);
+
+    // A share change only affects accrual from the change on.
+    set_timestamp(&env, 60);
+    client.mock_all_auths().set_recipient_shares(
+        &450,
+        &vec![
+            &env,
+            RecipientShare {
+                recipient: teammate.clone(),
+                share_bps: 10_000,
+            },
+        ],
+    );
+
+    set_timestamp(&env, 80);
+    assert_eq!(client.share_claimable(&450, &recipient), 60);
+    assert_eq!(client.share_claimable(&450, &teammate), 140);
+    client.mock_all_auths().withdraw_share(&450, &recipient, &60);
+    assert_eq!(token.balance(&recipient), 220);
+}
+
+#[test]
+fn test_dispute_split_pays_grantee_part_to_co_recipients_by_share() {
+    let env = Env::default();
+    let (client, grant_token, recipient, treasury, arbitrator) =
+        setup_disputable_grant(&env, 452);
+    let teammate = Address::generate(&env);
+    let token = TokenClient::new(&env, &grant_token);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().set_recipient_shares(
+        &452,
+        &vec![
+            &env,
+            RecipientShare {
+                recipient: recipient.clone(),
+                share_bps: 6_000,
+            },
+            RecipientShare {
+                recipient: teammate.clone(),
+                share_bps: 4_000,
+            },
+        ],
+    );
+
+    set_timestamp(&env, 40);
+    client.mock_all_auths().withdraw_share(&452, &teammate, &40);
+    client.mock_all_auths().open_dispute(&452, &recipient, &None);
+
+    // Half of each balance (recipient 160, teammate 0) and half of the unaccrued 800,
+    // the latter split 60/40.
+    client
+        .mock_all_auths()
+        .resolve_dispute(&452, &arbitrator, &DisputeResolution::Split(5_000));
+
+    assert_eq!(token.balance(&recipient), 80 + 240);
+    assert_eq!(token.balance(&teammate), 40 + 160);
+    assert_eq!(token.balance(&treasury), 480);
+    assert_eq!(token.balance(&client.address), 0);
+    assert_eq!(client.share_claimable(&452, &recipient), 0);
+    assert_eq!(client.share_claimable(&452, &teammate), 0);
+}
+
+#[test]
+fn test_clawback_on_split_grant_never_leaves_a_negative_share() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let teammate = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000_000);
+    create_simple_grant(&client, 453, &recipient, &grant_token, 1_000_000, SCALING_FACTOR);
+    client.mock_all_auths().set_recipient_shares(
+        &453,
+        &vec![
+            &env,
+            RecipientShare {
+                recipient: recipient.clone(),
+                share_bps: 9_000,
+            },
+            RecipientShare {
+                recipient: teammate.clone(),
+                share_bps: 1_000,
+            },
+        ],
+    );
+
+    // The teammate withdraws everything; the clawback takes the recipient's whole balance.
+    set_timestamp(&env, 500_000);
+    client
+        .mock_all_auths()
+        .withdraw_share(&453, &teammate, &50_000);
+    client.mock_all_auths().clawback(&453, &450_000, &1);
+
+    set_timestamp(&env, 500_000 + CLAWBACK_TIMELOCK_SECS);
+    client.mock_all_auths().execute_clawback(&453);
+
+    let recipient_share = client.share_claimable(&453, &recipient);
+    let teammate_share = client.share_claimable(&453, &teammate);
+    assert!(recipient_share >= 0);
+    assert!(teammate_share > 0);
+    assert_eq!(recipient_share + teammate_share, client.claimable(&453));
+}
+
+#[test]
+fn test_set_recipient_shares_rejects_invalid_split() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 451, &recipient, &grant_token, 1_000, SCALING_FACTOR);
+
+    let short = vec![
+        &env,
+        RecipientShare {
+            recipient: recipient.clone(),
+            share_bps: 9_000,
+        },
+    ];
+    assert_contract_error(
+        client.mock_all_auths().try_set_recipient_shares(&451, &short),
+        Error::InvalidShares,
+    );
+
+    let duplicated = vec![
+        &env,
+        RecipientShare {
+            recipient: recipient.clone(),
+            share_bps: 5_000,
+        },
+        RecipientShare {
+            recipient: recipient.clone(),
+            share_bps: 5_000,
+        },
+    ];
+    assert_contract_error(
+        client.mock_all_auths().try_set_recipient_shares(&451, &duplicated),
+        Error::InvalidShares,
+    );
+}
+
+#[test]
+fn test_subgrant_carves_rate_out_of_parent_and_is_cancelled_with_it() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let contributor = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 460, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 20);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_subgrant(&460, &contributor, &(6 * SCALING_FACTOR)),
+        Error::InvalidRate,
+    );
+    let subgrant_id = client
+        .mock_all_auths()
+        .create_subgrant(&460, &contributor, &(2 * SCALING_FACTOR));
+    assert_eq!(subgrant_id, 461);
+    assert_eq!(client.get_recipient_grants(&contributor), vec![&env, 461]);
+
+    // 900 unaccrued at 5/s; the sub-grant takes 2/5 of it and both end at t = 200.
+    let parent = client.get_grant(&460);
+    assert_eq!(parent.flow_rate, 3 * SCALING_FACTOR);
+    assert_eq!(parent.total_amount, 640);
+    let subgrant = client.get_grant(&461);
+    assert_eq!(subgrant.total_amount, 360);
+    assert_eq!(subgrant.parent_id, Some(460));
+
+    set_timestamp(&env, 40);
+    assert_eq!(client.claimable(&460), 160);
+    assert_eq!(client.claimable(&461), 40);
+
+    client.mock_all_auths().cancel_grant(&460);
+    let subgrant = client.get_grant(&461);
+    assert_eq!(subgrant.status, GrantStatus::Cancelled);
+    assert_eq!(subgrant.claimable, 40);
+
+    client.mock_all_auths().withdraw(&461, &40);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&contributor), 40);
+}
+
+#[test]
+fn test_subgrant_locks_parent_rate_and_follows_parent_disputes() {
+    let env = Env::default();
+    let (client, admin, _) = setup_contract(&env);
+    let recipient = Address::generate(&env);
+    let contributor = Address::generate(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+
+    set_timestamp(&env, 0);
+    create_simple_grant(&client, 462, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+    set_timestamp(&env, 20);
+    let subgrant_id = client
+        .mock_all_auths()
+        .create_subgrant(&462, &contributor, &(2 * SCALING_FACTOR));
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_propose_rate_change(&462, &SCALING_FACTOR, &None),
+        Error::InvalidState,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_batch_update_rate(&vec![&env, (462, SCALING_FACTOR)]),
+        Error::InvalidState,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_apply_kpi_multiplier(&462, &2),
+        Error::InvalidState,
+    );
+
+    // A dispute on the parent freezes the sub-grant until it is resumed.
+    client.mock_all_auths().open_dispute(&462, &admin, &None);
+    set_timestamp(&env, 40);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&subgrant_id, &10),
+        Error::UnderDispute,
+    );
+    client
+        .mock_all_auths()
+        .resolve_dispute(&462, &admin, &DisputeResolution::Resume);
+    assert_eq!(client.get_grant(&subgrant_id).dispute, Dispute::default());
+    client.mock_all_auths().withdraw(&subgrant_id, &40);
+
+    // Once the sub-grant is gone, the parent's rate can change again.
+    client.mock_all_auths().cancel_grant(&subgrant_id);
+    client
+        .mock_all_auths()
+        .propose_rate_change(&462, &SCALING_FACTOR, &None);
+}
+
+#[test]
+fn test_withdraw_to_pays_destination_with_recipient_auth() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let exchange = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    let token = TokenClient::new(&env, &grant_token);
+    create_simple_grant(&client, 470, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw_to(&470, &60, &exchange);
+
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, recipient);
+
+    assert_eq!(token.balance(&exchange), 60);
+    assert_eq!(token.balance(&recipient), 0);
+    assert_eq!(client.get_grant(&470).withdrawn, 60);
+    assert_eq!(client.claimable(&470), 40);
+}
+
+#[test]
+fn test_claim_delegate_withdraws_to_recipient_until_revoked() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    let token = TokenClient::new(&env, &grant_token);
+    create_simple_grant(&client, 480, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 20);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_withdraw_as_delegate(&480, &relayer, &10),
+        Error::NotAuthorized,
+    );
+
+    client.mock_all_auths().set_claim_delegate(&480, &relayer);
+    client
+        .mock_all_auths()
+        .withdraw_as_delegate(&480, &relayer, &60);
+
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, relayer);
+    assert_eq!(token.balance(&recipient), 60);
+    assert_eq!(token.balance(&relayer), 0);
+
+    client.mock_all_auths().revoke_claim_delegate(&480);
+    assert_eq!(client.get_grant(&480).claim_delegate, None);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_withdraw_as_delegate(&480, &relayer, &10),
+        Error::NotAuthorized,
+    );
+}
+
+#[test]
+fn test_process_payouts_pushes_claimable_once_interval_elapses() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 10_000);
+    let token = TokenClient::new(&env, &grant_token);
+    create_simple_grant(&client, 490, &recipient, &grant_token, 5_000, 5 * SCALING_FACTOR);
+    create_simple_grant(&client, 491, &recipient, &grant_token, 5_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().set_payout_interval(&490, &100);
+    client
+        .mock_all_auths()
+        .set_payout_fee(&grant_token, &100, &100);
+
+    set_timestamp(&env, 50);
+    assert!(client.mock_all_auths().process_payouts(&keeper, &0, &10).is_empty());
+
+    // Only the opted-in grant is paid; the keeper's 1% comes out of the payout.
+    set_timestamp(&env, 100);
+    let paid = client.mock_all_auths().process_payouts(&keeper, &0, &10);
+    assert_eq!(
+        paid,
+        vec![
+            &env,
+            GrantWithdrawal {
+                grant_id: 490,
+                token: grant_token.clone(),
+                amount: 500,
+            }
+        ]
+    );
+    assert_eq!(token.balance(&recipient), 495);
+    assert_eq!(token.balance(&keeper), 5);
+    assert_eq!(client.claimable(&491), 500);
+
+    // A keeper payout doesn't count as grantee activity.
+    let grant = client.get_grant(&490);
+    assert_eq!(grant.last_claim_time, 0);
+    assert_eq!(grant.last_payout_at, 100);
+
+    set_timestamp(&env, 150);
+    assert!(client.mock_all_auths().process_payouts(&keeper, &0, &10).is_empty());
+
+    set_timestamp(&env, 200);
+    assert!(client.mock_all_auths().process_payouts(&keeper, &1, &10).is_empty());
+    assert_eq!(client.mock_all_auths().process_payouts(&keeper, &0, &1).len(), 1);
+    assert_eq!(token.balance(&recipient), 990);
+}
+
+#[test]
+fn test_process_payouts_skips_grants_that_cannot_be_paid() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let keeper = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    // The contract holds none of the first token, so paying its grant fails.
+    let unfunded_token = setup_funded_token(&env, &admin, &client.address, 0);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 5_000);
+    create_simple_grant(&client, 492, &recipient, &unfunded_token, 5_000, 5 * SCALING_FACTOR);
+    create_simple_grant(&client, 493, &recipient, &grant_token, 5_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().set_payout_interval(&492, &100);
+    client.mock_all_auths().set_payout_interval(&493, &100);
+
+    set_timestamp(&env, 100);
+    let paid = client.mock_all_auths().process_payouts(&keeper, &0, &10);
+    assert_eq!(paid.len(), 1);
+    assert_eq!(paid.get(0).unwrap().grant_id, 493);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&recipient), 500);
+
+    let skipped = client.get_grant(&492);
+    assert_eq!(skipped.claimable, 500);
+    assert_eq!(skipped.withdrawn, 0);
+    assert_eq!(skipped.last_payout_at, 0);
+}
+
+#[test]
+fn test_process_payouts_skips_keeper_fee_that_cannot_be_paid() {
+    let env = Env::default();
+    let (client, admin, _) = setup_contract(&env);
+    let recipient = Address::generate(&env);
+    let keeper = Address::generate(&env);
+    let sac = env.register_stellar_asset_contract_v2(admin.clone());
+    sac.issuer().set_flag(IssuerFlags::RevocableFlag);
+    let grant_token = sac.address();
+    StellarAssetClient::new(&env, &grant_token)
+        .mock_all_auths()
+        .mint(&client.address, &5_000);
+    let token = TokenClient::new(&env, &grant_token);
+
+    set_timestamp(&env, 0);
+    create_simple_grant(&client, 494, &recipient, &grant_token, 5_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().set_payout_interval(&494, &100);
+    client
+        .mock_all_auths()
+        .set_payout_fee(&grant_token, &100, &100);
+    // The keeper can't receive the token, so paying its fee fails.
+    StellarAssetClient::new(&env, &grant_token)
+        .mock_all_auths()
+        .set_authorized(&keeper, &false);
+
+    set_timestamp(&env, 100);
+    let paid = client.mock_all_auths().process_payouts(&keeper, &0, &10);
+    assert_eq!(paid.get(0).unwrap().amount, 495);
+    assert_eq!(token.balance(&recipient), 495);
+    assert_eq!(token.balance(&keeper), 0);
+
+    // The unpaid fee stays with the grant.
+    let grant = client.get_grant(&494);
+    assert_eq!(grant.withdrawn, 495);
+    assert_eq!(grant.claimable, 5);
+}
+
+#[test]
+fn test_transfer_position_moves_withdrawal_rights_to_new_owner() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &client.address, 1_000);
+    create_simple_grant(&client, 500, &recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+
+    set_timestamp(&env, 20);
+    assert_contract_error(
+        client.mock_all_auths().try_transfer_position(&500, &buyer, &buyer),
+        Error::NotAuthorized,
+    );
+    client.mock_all_auths().transfer_position(&500, &recipient, &buyer);
+
+    assert_eq!(client.owner_of(&500), buyer);
+    assert_eq!(client.get_recipient_grants(&recipient).len(), 0);
+    assert_eq!(client.get_recipient_grants(&buyer), vec![&env, 500]);
+
+    // The claimable balance accrued before the sale goes with the position.
+    client.mock_all_auths().withdraw(&500, &100);
+    assert_eq!(env.auths()[0].0, buyer);
+    assert_eq!(TokenClient::new(&env, &grant_token).balance(&buyer), 100);
+}
+
+#[test]
+fn test_non_transferable_grant_rejects_transfer_position() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    create_simple_grant(&client, 501, &recipient, &grant_token, 1_000, SCALING_FACTOR);
+    client.mock_all_auths().set_transferable(&501, &false);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_transfer_position(&501, &recipient, &buyer),
+        Error::NotTransferable,
+    );
+    assert_eq!(client.owner_of(&501), recipient);
+}
+
+mod payment_hook {
+    use soroban_sdk::{contract, contractimpl, symbol_short, Env};
+
+    #[contract]
+    pub struct HookReceiver;
+
+    #[contractimpl]
+    impl HookReceiver {
+        pub fn on_grant_payment(env: Env, grant_id: u64, amount: i128) {
+            env.storage()
+                .instance()
+                .set(&symbol_short!("last"), &(grant_id, amount));
+        }
+
+        pub fn last_payment(env: Env) -> Option<(u64, i128)> {
+            env.storage().instance().get(&symbol_short!("last"))
+        }
+    }
+}
+
+mod failing_payment_hook {
+    use soroban_sdk::{contract, contractimpl, Env};
+
+    #[contract]
+    pub struct FailingHookReceiver;
+
+    #[contractimpl]
+    impl FailingHookReceiver {
+        pub fn on_grant_payment(_env: Env, _grant_id: u64, _amount: i128) {
+            panic!("hook failed");
+        }
+    }
+}
+
+fn setup_hook_grant<'a>(
+    env: &'a Env,
+    grant_id: u64,
+    recipient: &Address,
+    hook: PaymentHook,
+) -> (GrantContractClient<'a>, TokenClient<'a>) {
+    let (client, admin, _) = setup_contract(env);
+    let grant_token = setup_funded_token(env, &admin, &client.address, 1_000);
+
+    create_simple_grant(&client, grant_id, recipient, &grant_token, 1_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().set_payment_hook(&grant_id, &hook);
+
+    (client, TokenClient::new(env, &grant_token))
+}
+
+#[test]
+fn test_withdraw_calls_recipient_payment_hook() {
+    let env = Env::default();
+    let receiver = env.register(payment_hook::HookReceiver, ());
+    let (client, token) = setup_hook_grant(&env, 510, &receiver, PaymentHook::Required);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&510, &60);
+
+    assert_eq!(token.balance(&receiver), 60);
+    let receiver_client = payment_hook::HookReceiverClient::new(&env, &receiver);
+    assert_eq!(receiver_client.last_payment(), Some((510, 60)));
+}
+
+#[test]
+fn test_failing_payment_hook_reverts_only_when_required() {
+    let env = Env::default();
+    let receiver = env.register(failing_payment_hook::FailingHookReceiver, ());
+
+    let (client, token) = setup_hook_grant(&env, 511, &receiver, PaymentHook::BestEffort);
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&511, &60);
+    assert_eq!(token.balance(&receiver), 60);
+
+    client
+        .mock_all_auths()
+        .set_payment_hook(&511, &PaymentHook::Required);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&511, &40),
+        Error::PaymentHookFailed,
+    );
+    assert_eq!(token.balance(&receiver), 60);
+    assert_eq!(client.claimable(&511), 40);
+}
+
+#[test]
+fn test_payment_hook_runs_on_withdraw_all_and_keeper_payouts() {
+    let env = Env::default();
+    let receiver = env.register(payment_hook::HookReceiver, ());
+    let receiver_client = payment_hook::HookReceiverClient::new(&env, &receiver);
+    let keeper = Address::generate(&env);
+    let (client, _) = setup_hook_grant(&env, 512, &receiver, PaymentHook::BestEffort);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw_all(&receiver);
+    assert_eq!(receiver_client.last_payment(), Some((512, 100)));
+
+    client.mock_all_auths().set_payout_interval(&512, &100);
+    set_timestamp(&env, 120);
+    client.mock_all_auths().process_payouts(&keeper, &0, &10);
+    assert_eq!(receiver_client.last_payment(), Some((512, 500)));
+
+    // A required hook can't be combined with batch payouts.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_payment_hook(&512, &PaymentHook::Required),
+        Error::InvalidState,
+    );
+}
+
+#[test]
+fn test_withdraw_all_tells_hook_the_amount_received_after_fees() {
+    let env = Env::default();
+    let receiver = env.register(payment_hook::HookReceiver, ());
+    let receiver_client = payment_hook::HookReceiverClient::new(&env, &receiver);
+    let (client, admin, _) = setup_contract(&env);
+    let token = FeeTokenClient::new(&env, &env.register(FeeToken, ()));
+    token.mint(&admin, &1_000);
+
+    create_simple_grant(&client, 514, &receiver, &token.address, 1_000, 5 * SCALING_FACTOR);
+    client.mock_all_auths().fund_grant(&514, &admin, &1_000);
+    client
+        .mock_all_auths()
+        .set_payment_hook(&514, &PaymentHook::BestEffort);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw_all(&receiver);
+    assert_eq!(token.balance(&receiver), 99);
+    assert_eq!(receiver_client.last_payment(), Some((514, 99)));
+}
+
+#[test]
+fn test_transfer_position_turns_payment_hook_off() {
+    let env = Env::default();
+    let receiver = env.register(payment_hook::HookReceiver, ());
+    let new_owner = Address::generate(&env);
+    let (client, token) = setup_hook_grant(&env, 513, &receiver, PaymentHook::Required);
+
+    client
+        .mock_all_auths()
+        .transfer_position(&513, &receiver, &new_owner);
+    assert_eq!(client.get_grant(&513).payment_hook, PaymentHook::Off);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&513, &100);
+    assert_eq!(token.balance(&new_owner), 100);
+}
+
+fn grant_params(
+    grant_id: u64,
+    recipient: &Address,
+    token: &Address,
+    total_amount: i128,
+) -> GrantParams {
+    GrantParams {
+        grant_id,
+        recipient: recipient.clone(),
+        token: token.clone(),
+        total_amount,
+        flow_rate: 5 * SCALING_FACTOR,
+        warmup_duration: 0,
+        warmup_curve: DEFAULT_WARMUP_CURVE,
+        rate_schedule: None,
+    }
+}
+
+#[test]
+fn test_batch_create_grants_funds_all_with_one_transfer() {
+    let env = Env::default();
+    let funder = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    let (client, admin, _) = setup_contract(&env);
+    let grant_token = setup_funded_token(&env, &admin, &funder, 5_000);
+    let token = TokenClient::new(&env, &grant_token);
+    client.mock_all_auths().batch_create_grants(
+        &vec![
+            &env,
+            grant_params(520, &alice, &grant_token, 1_000),
+            grant_params(521, &bob, &grant_token, 2_000),
+        ],
+        &Some(funder.clone()),
+    );
+
+    assert_eq!(token.balance(&funder), 2_000);
+    assert_eq!(token.balance(&client.address), 3_000);
+    assert_eq!(client.get_grant(&520).funded, 1_000);
+    assert_eq!(client.get_grant(&521).funded, 2_000);
+    assert_eq!(client.get_recipient_grants(&bob), vec![&env, 521]);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&521, &50);
+    assert_eq!(token.balance(&bob), 50);
+}
+
+#[test]
+fn test_batch_create_grants_is_all_or_nothing() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+
+    let grants = vec![
+        &env,
+        grant_params(522, &recipient, &grant_token, 1_000),
+        grant_params(523, &recipient, &grant_token, 0),
+    ];
+    assert_contract_error(
+        client.mock_all_auths().try_batch_create_grants(&grants, &None),
+        Error::InvalidAmount,
+    );
+    assert_contract_error(client.try_get_grant(&522), Error::GrantNotFound);
+}
+
+#[test]
+fn test_batch_cancel_and_update_rate_apply_to_every_grant() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let grant_token = Address::generate(&env);
+
+    let (client, _, _) = setup_contract(&env);
+    for grant_id in 524..528 {
+        create_simple_grant(&client, grant_id, &recipient, &grant_token, 1_000, SCALING_FACTOR);
+    }
+
+    client
+        .mock_all_auths()
+        .batch_update_rate(&vec![&env, (524, 3 * SCALING_FACTOR), (525, 0)]);
+    assert_eq!(client.get_grant(&524).pending_rate, 3 * SCALING_FACTOR);
+    assert_eq!(client.get_grant(&525).flow_rate, 0);
+
+    client.mock_all_auths().batch_cancel(&vec![&env, 526, 527]);
+    assert_eq!(client.get_grant(&526).status, GrantStatus::Cancelled);
+    assert_eq!(client.get_grant(&527).status, GrantStatus::Cancelled);
+
+    // 527 is already cancelled, so nothing in this batch is cancelled.
+    assert_contract_error(
+        client.mock_all_auths().try_batch_cancel(&vec![&env, 524, 527]),
+        Error::InvalidState,
+    );
+    assert_eq!(client.get_grant(&524).status, GrantStatus::Active);
+}
+
+fn setup_program(
+    env: &Env,
+    program_id: u64,
+    budget: i128,
+    revocable: bool,
+) -> (GrantContractClient<'_>, Address) {
+    let (client, _, _) = setup_contract(env);
+    let manager = Address::generate(env);
+    let grant_token = Address::generate(env);
+
+    client.mock_all_auths().create_program(
+        &program_id,
+        &String::from_str(env, "Q3 Infrastructure Round"),
+        &manager,
+        &grant_token,
+        &budget,
+        &0,
+        &(90 * DAY_SECS),
+        &ProgramDefaults {
+            warmup_duration: 0,
+            warmup_curve: DEFAULT_WARMUP_CURVE,
+            inactivity_threshold: 30 * DAY_SECS,
+            revocable,
+        },
+    );
+
+    (client, grant_token)
+}
+
+#[test]
+fn test_program_grants_stay_within_budget() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let (client, grant_token) = setup_program(&env, 1, 3_000, true);
+
+    client
+        .mock_all_auths()
+        .create_program_grant(&1, &530, &recipient, &2_000, &SCALING_FACTOR);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_program_grant(&1, &531, &recipient, &1_001, &SCALING_FACTOR),
+        Error::BudgetExceeded,
+    );
+    client
+        .mock_all_auths()
+        .create_program_grant(&1, &531, &recipient, &1_000, &SCALING_FACTOR);
+
+    let grant = client.get_grant(&530);
+    assert_eq!(grant.program_id, Some(1));
+    assert_eq!(grant.token, grant_token);
+    assert_eq!(grant.inactivity_threshold, 30 * DAY_SECS);
+    assert_eq!(client.program_budget_used(&1), 3_000);
+    assert_eq!(client.program_allocated_funds(&1), 3_000);
+
+    assert_contract_error(
+        client.mock_all_auths().try_set_program_budget(&1, &2_999),
+        Error::BudgetExceeded,
+    );
+    client.mock_all_auths().set_program_budget(&1, &5_000);
+    assert_eq!(client.get_program(&1).budget, 5_000);
+}
+
+#[test]
+fn test_program_rejects_grants_outside_its_dates() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let (client, _) = setup_program(&env, 2, 3_000, true);
+
+    set_timestamp(&env, 90 * DAY_SECS);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_program_grant(&2, &532, &recipient, &1_000, &SCALING_FACTOR),
+        Error::ProgramNotOpen,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_program_grant(&3, &532, &recipient, &1_000, &SCALING_FACTOR),
+        Error::ProgramNotFound,
+    );
+}
+
+#[test]
+fn test_program_default_can_make_grants_non_revocable() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+    let (client, _) = setup_program(&env, 4, 3_000, false);
+
+    client
+        .mock_all_auths()
+        .create_program_grant(&4, &533, &recipient, &1_000, &SCALING_FACTOR);
+    assert!(!client.get_grant(&533).revocable);
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_grant(&533),
+        Error::NotRevocable,
+    );
+}
+
+fn setup_qf_round(
+    env: &Env,
+    matching_pool: i128,
+) -> (GrantContractClient<'_>, Address, Address) {
+    let (client, admin, treasury) = setup_contract(env);
+    let funder = Address::generate(env);
+    let token = setup_funded_token(env, &admin, &funder, matching_pool);
+
+    client.mock_all_auths().create_qf_round(
+        &1,
+        &token,
+        &matching_pool,
+        &funder,
+        &0,
+        &(7 * DAY_SECS),
+        &(30 * DAY_SECS),
+    );
+
+    (client, token, treasury)
+}
+
+fn qf_contribute(
+    env: &Env,
+    client: &GrantContractClient,
+    token: &Address,
+    project: &Address,
+    amount: i128,
+) {
+    let contributor = Address::generate(env);
+    StellarAssetClient::new(env, token)
+        .mock_all_auths()
+        .mint(&contributor, &amount);
+    client
+        .mock_all_auths()
+        .contribute(&1, project, &contributor, &amount);
+}
+
+#[test]
+fn test_qf_round_streams_quadratic_matches_as_grants() {
+    let env = Env::default();
+    let (client, token, treasury) = setup_qf_round(&env, 10_000);
+    let project_a = Address::generate(&env);
+    let project_b = Address::generate(&env);
+    let stranger = Address::generate(&env);
+
+    client.mock_all_auths().add_qf_project(&1, &project_a);
+    client.mock_all_auths().add_qf_project(&1, &project_b);
+    assert_contract_error(
+        client.mock_all_auths().try_add_qf_project(&1, &project_a),
+        Error::InvalidState,
+    );
+
+    for _ in 0..4 {
+        qf_contribute(&env, &client, &token, &project_a, 100);
+    }
+    qf_contribute(&env, &client, &token, &project_b, 400);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_contribute(&1, &stranger, &treasury, &100),
+        Error::UnknownProject,
+    );
+    assert_eq!(client.qf_contributions(&1, &project_a), 400);
+    assert_eq!(TokenClient::new(&env, &token).balance(&project_a), 400);
+    let ttl = env.as_contract(&client.address, || {
+        env.storage()
+            .persistent()
+            .get_ttl(&DataKey::QfContributions(1, project_a.clone()))
+    });
+    assert!(ttl >= 15 * 17_280);
+
+    assert_contract_error(client.try_close_qf_round(&1), Error::QfRoundNotOpen);
+
+    set_timestamp(&env, 7 * DAY_SECS);
+    let matches = client.close_qf_round(&1);
+    assert_eq!(
+        matches,
+        vec![
+            &env,
+            QfMatch {
+                project: project_a.clone(),
+                contributions: 400,
+                match_amount: 1_200,
+                grant_id: Some(0),
+            },
+            QfMatch {
+                project: project_b,
+                contributions: 400,
+                match_amount: 0,
+                grant_id: None,
+            },
+        ]
+    );
+
+    // Many small contributions earn the match; a single large one earns none.
+    let grant = client.get_grant(&0);
+    assert_eq!(grant.recipient, project_a);
+    assert_eq!(grant.total_amount, 1_200);
+    assert_eq!(grant.funded, 1_200);
+    assert!(grant.flow_rate * i128::from(30 * DAY_SECS) >= 1_200 * SCALING_FACTOR);
+    assert_eq!(TokenClient::new(&env, &token).balance(&treasury), 8_800);
+
+    assert!(client.get_qf_round(&1).closed);
+    assert_contract_error(client.try_close_qf_round(&1), Error::QfRoundClosed);
+}
+
+#[test]
+fn test_qf_matches_scale_down_to_a_capped_pool() {
+    let env = Env::default();
+    let (client, token, treasury) = setup_qf_round(&env, 700);
+    let project_a = Address::generate(&env);
+    let project_b = Address::generate(&env);
+
+    client.mock_all_auths().add_qf_project(&1, &project_a);
+    client.mock_all_auths().add_qf_project(&1, &project_b);
+    for _ in 0..4 {
+        qf_contribute(&env, &client, &token, &project_a, 100);
+    }
+    for _ in 0..2 {
+        qf_contribute(&env, &client, &token, &project_b, 100);
+    }
+
+    set_timestamp(&env, 7 * DAY_SECS);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_contribute(&1, &project_a, &treasury, &100),
+        Error::QfRoundNotOpen,
+    );
+
+    // Uncapped matches are 1200 and 200; the 700 pool pays them 6/7 and 1/7 of it.
+    let matches = client.close_qf_round(&1);
+    assert_eq!(matches.get(0).unwrap().match_amount, 600);
+    assert_eq!(matches.get(1).unwrap().match_amount, 100);
+    assert_eq!(client.get_grant(&0).total_amount, 600);
+    assert_eq!(client.get_grant(&1).total_amount, 100);
+    assert_eq!(TokenClient::new(&env, &token).balance(&treasury), 0);
+}
+
+#[test]
+fn test_rescue_tokens_cannot_take_an_open_matching_pool() {
+    let env = Env::default();
+    let (client, token, treasury) = setup_qf_round(&env, 1_000);
+    let to = Address::generate(&env);
+    StellarAssetClient::new(&env, &token)
+        .mock_all_auths()
+        .mint(&client.address, &50);
+
+    // Only the stray 50 sits outside the open round's pool.
+    assert_contract_error(
+        client.mock_all_auths().try_rescue_tokens(&token, &51, &to),
+        Error::RescueWouldViolateAllocated,
+    );
+    client.mock_all_auths().rescue_tokens(&token, &50, &to);
+    assert_eq!(TokenClient::new(&env, &token).balance(&to), 50);
+
+    // With no projects, closing the round returns the whole pool to the treasury.
+    set_timestamp(&env, 7 * DAY_SECS);
+    client.close_qf_round(&1);
+    assert_eq!(TokenClient::new(&env, &token).balance(&treasury), 1_000);
 }
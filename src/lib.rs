@@ -1,6 +1,7 @@
 #![allow(unexpected_cfgs)]
 #![no_std]
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     loop {}